@@ -1,3 +1,18 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Take};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::errors::SwishError;
+
+/// Size of the chunks sent by the SwissTransfer web client
+pub const DEFAULT_CHUNK_SIZE: usize = 50 * 1024 * 1024;
+/// Smaller chunks mean so many requests the server starts throttling them
+pub const MIN_CHUNK_SIZE: usize = 1024 * 1024;
+/// The server is not known to accept anything larger, keep some margin from what the web client sends
+pub const MAX_CHUNK_SIZE: usize = 100 * 1024 * 1024;
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub index: usize,
@@ -13,11 +28,73 @@ impl Chunk {
             size,
         }
     }
+
+    /// Reader over the bytes of this chunk in the file at `path`, and nothing more.
+    /// Every chunk gets its own file handle so chunks can be read in any order, or at once
+    pub fn reader(&self, path: &Path) -> std::io::Result<Take<File>> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(self.offset as u64))?;
+        Ok(file.take(self.size as u64))
+    }
+
+    /// SHA-256 of the bytes of this chunk in the file at `path`, in hexadecimal
+    pub fn sha256(&self, path: &Path) -> std::io::Result<String> {
+        let mut reader = Checksummed::new(self.reader(path)?);
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok(reader.finish())
+    }
+}
+
+/// Reads through `inner`, computing the SHA-256 of what went through
+pub struct Checksummed<R> {
+    inner: R,
+    hasher: Sha256,
+    /// Bytes read so far
+    pub read: u64,
+}
+
+impl<R> Checksummed<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            read: 0,
+        }
+    }
+
+    /// SHA-256 of everything read, in hexadecimal
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// Make sure the server will accept chunks of `chunk_size` bytes
+pub fn validate_chunk_size(chunk_size: usize) -> Result<(), SwishError> {
+    if (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        Ok(())
+    } else {
+        Err(SwishError::InvalidChunkSize {
+            size: chunk_size,
+            min: MIN_CHUNK_SIZE,
+            max: MAX_CHUNK_SIZE,
+        })
+    }
 }
 
+/// Split a file of `size` bytes in chunks of `chunk_size` bytes, the last one being shorter if needed.
+/// An empty file still gets a single empty chunk as only the last chunk completes a file on the server
 pub fn build_chunks_array(size: usize, chunk_size: usize) -> Vec<Chunk> {
     let mut chunks = Vec::new();
-    let num_chunks = (size as f64 / chunk_size as f64).ceil() as usize;
+    let num_chunks = size.div_ceil(chunk_size).max(1);
     for i in 0..num_chunks {
         let offset = i * chunk_size;
         let size = if offset + chunk_size <= size {
@@ -0,0 +1,479 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+use curl::easy::{Easy2, Handler, HttpVersion, List, ReadError, WriteError};
+use curl::multi::{Easy2Handle, Multi, MultiWaker};
+use tokio::time::MissedTickBehavior;
+
+use crate::api::transport::{Body, Method, Request, Sending, Sink, Transport};
+use crate::errors::SwishError;
+
+/// Bytes of a request body read ahead for curl to send
+const BODY_BUFFER: usize = 256 * 1024;
+
+/// Bytes of an answer waiting for their sink past which curl stops reading the connection
+const RECEIVE_BUFFER: usize = 1024 * 1024;
+
+/// How often `progress` is called when nothing moves
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest wait of the curl thread, it is woken up sooner for anything to do
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When a request is given up, it then fails with a timeout that gets retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeouts {
+    /// Longest wait for the connection to the server
+    pub connect: Duration,
+    /// A request moving less than this many bytes per second for `low_speed_time` is aborted,
+    /// waiting for the answer counts as not moving
+    pub low_speed_limit: u32,
+    pub low_speed_time: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(30),
+            low_speed_limit: 1,
+            low_speed_time: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Sends requests with libcurl, what Swish uses unless told otherwise.
+/// A single thread runs every request of the transport on curl's multi interface, however many
+/// transfers use it, and connections get reused. Requests are futures waiting for that thread,
+/// they need a tokio runtime with its timer enabled
+#[derive(Default)]
+pub struct CurlTransport {
+    timeouts: Timeouts,
+    /// Started along with the first request
+    driver: Mutex<Option<Driver>>,
+    /// Identifies each request to the curl thread
+    next_token: AtomicUsize,
+}
+
+impl CurlTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeouts(timeouts: Timeouts) -> Self {
+        Self {
+            timeouts,
+            driver: Mutex::default(),
+            next_token: AtomicUsize::default(),
+        }
+    }
+
+    /// Hand `command` to the curl thread, starting it if needed
+    fn command(&self, command: Command) -> Result<(), SwishError> {
+        let mut driver = self.driver.lock().unwrap();
+        let driver = match driver.as_mut() {
+            Some(driver) => driver,
+            None => driver.insert(Driver::start()?),
+        };
+        driver.commands.send(command).map_err(|_| stopped())?;
+        driver.waker.wakeup().map_err(|_| stopped())
+    }
+
+    fn easy(&self, request: &Request, exchange: Exchange) -> Result<Easy2<Exchange>, SwishError> {
+        let mut easy = Easy2::new(exchange);
+        let mut headers = List::new();
+        for header in &request.headers {
+            headers.append(header)?;
+        }
+        easy.url(&request.url)?;
+        easy.http_headers(headers)?;
+        easy.progress(true)?;
+        easy.connect_timeout(self.timeouts.connect)?;
+        easy.low_speed_limit(self.timeouts.low_speed_limit)?;
+        easy.low_speed_time(self.timeouts.low_speed_time)?;
+
+        if request.method == Method::Post {
+            easy.post(true)?;
+            match &request.body {
+                Body::Empty => easy.post_field_size(0)?,
+                Body::Bytes(bytes) => easy.post_fields_copy(bytes)?,
+                Body::Stream { size, .. } => {
+                    easy.post_field_size(*size)?;
+                    easy.http_version(HttpVersion::V11)?;
+                }
+            }
+        }
+        Ok(easy)
+    }
+}
+
+impl Drop for CurlTransport {
+    fn drop(&mut self) {
+        if let Some(driver) = self.driver.get_mut().unwrap().take() {
+            let _ = driver.commands.send(Command::Stop);
+            let _ = driver.waker.wakeup();
+        }
+    }
+}
+
+impl Transport for CurlTransport {
+    fn send<'a>(
+        &'a self,
+        request: Request<'a>,
+        sink: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32> {
+        Box::pin(async move {
+            let exchange = Exchange::default();
+            let shared = exchange.0.clone();
+            let easy = self.easy(&request, exchange)?;
+            let mut reader = match request.body {
+                Body::Stream { reader, .. } => Some(reader),
+                _ => {
+                    shared.lock().unwrap().body_read = true;
+                    None
+                }
+            };
+
+            if let Some(reader) = reader.as_mut() {
+                fill_body(&shared, *reader)?;
+            }
+            let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+            self.command(Command::Start(token, easy))?;
+            let mut running = Running {
+                transport: self,
+                token,
+                over: false,
+            };
+            let mut tick = tokio::time::interval(PROGRESS_INTERVAL);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                let mut resume = false;
+                if let Some(reader) = reader.as_mut() {
+                    resume |= fill_body(&shared, *reader)?;
+                }
+
+                let (received, result, sent, received_bytes) = {
+                    let mut shared = shared.lock().unwrap();
+                    resume |= std::mem::take(&mut shared.write_paused);
+                    shared.buffered = 0;
+                    (
+                        std::mem::take(&mut shared.received),
+                        shared.result.take(),
+                        shared.sent,
+                        shared.received_bytes,
+                    )
+                };
+                for received in received {
+                    match received {
+                        Received::Status(status) if !sink.status(status) => {
+                            return Err(SwishError::InvalidResponse {
+                                response: format!("the answer {} was refused", status),
+                            })
+                        }
+                        Received::Status(_) => (),
+                        Received::Data(data) => sink.write(&data)?,
+                    }
+                }
+                if let Some(result) = result {
+                    running.over = true;
+                    progress(sent, received_bytes);
+                    return result;
+                }
+                if resume {
+                    self.command(Command::Resume(token))?;
+                }
+                if !progress(sent, received_bytes) {
+                    return Err(SwishError::Interrupted);
+                }
+
+                // Until curl has something for us, or it is time to call `progress` again
+                poll_fn(|context| {
+                    if tick.poll_tick(context).is_ready() {
+                        return Poll::Ready(());
+                    }
+                    let mut shared = shared.lock().unwrap();
+                    if shared.has_news() {
+                        return Poll::Ready(());
+                    }
+                    shared.waker = Some(context.waker().clone());
+                    Poll::Pending
+                })
+                .await;
+            }
+        })
+    }
+}
+
+fn stopped() -> SwishError {
+    SwishError::CurlError {
+        error: curl::Error::new(curl_sys::CURLE_FAILED_INIT),
+    }
+}
+
+/// The curl thread of a transport
+struct Driver {
+    commands: Sender<Command>,
+    waker: MultiWaker,
+}
+
+impl Driver {
+    fn start() -> Result<Self, SwishError> {
+        let (commands, receiver) = mpsc::channel();
+        let (waker_sender, waker) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("swish-curl".to_string())
+            .spawn(move || {
+                let multi = Multi::new();
+                if waker_sender.send(multi.waker()).is_ok() {
+                    drive(multi, receiver);
+                }
+            })?;
+        Ok(Self {
+            commands,
+            waker: waker.recv().map_err(|_| stopped())?,
+        })
+    }
+}
+
+enum Command {
+    /// Add the request with this token
+    Start(usize, Easy2<Exchange>),
+    /// Data can move again for the request with this token
+    Resume(usize),
+    /// Nobody waits for the request with this token anymore
+    Abort(usize),
+    /// The transport is gone
+    Stop,
+}
+
+/// Run the requests `commands` bring until the transport is dropped
+fn drive(multi: Multi, commands: Receiver<Command>) {
+    let mut requests: HashMap<usize, Easy2Handle<Exchange>> = HashMap::new();
+    loop {
+        // Nothing runs, there is no point in waking up before the next command
+        let mut command = match requests.is_empty() {
+            true => commands.recv().map_err(|_| TryRecvError::Disconnected),
+            false => commands.try_recv(),
+        };
+        loop {
+            match command {
+                Ok(Command::Start(token, easy)) => {
+                    let shared = easy.get_ref().0.clone();
+                    match multi.add2(easy) {
+                        Ok(mut handle) => match handle.set_token(token) {
+                            Ok(()) => {
+                                requests.insert(token, handle);
+                            }
+                            Err(error) => {
+                                let _ = multi.remove2(handle);
+                                shared.lock().unwrap().finish(Err(error.into()));
+                            }
+                        },
+                        Err(_) => shared.lock().unwrap().finish(Err(stopped())),
+                    }
+                }
+                Ok(Command::Resume(token)) => {
+                    if let Some(handle) = requests.get(&token) {
+                        let _ = handle.unpause_read();
+                        let _ = handle.unpause_write();
+                    }
+                }
+                Ok(Command::Abort(token)) => {
+                    if let Some(handle) = requests.remove(&token) {
+                        let _ = multi.remove2(handle);
+                    }
+                }
+                Ok(Command::Stop) | Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => break,
+            }
+            command = commands.try_recv();
+        }
+
+        if let Err(error) = multi.perform() {
+            log::error!("curl failed to run the requests: {}", error);
+        }
+        let mut over = Vec::new();
+        multi.messages(|message| {
+            let Ok(token) = message.token() else { return };
+            if let Some(result) = requests
+                .get(&token)
+                .and_then(|handle| message.result_for2(handle))
+            {
+                over.push((token, result));
+            }
+        });
+        for (token, result) in over {
+            if let Some(handle) = requests.remove(&token) {
+                let result = result.and_then(|()| handle.response_code());
+                let shared = handle.get_ref().0.clone();
+                let _ = multi.remove2(handle);
+                shared
+                    .lock()
+                    .unwrap()
+                    .finish(result.map_err(SwishError::from));
+            }
+        }
+
+        if !requests.is_empty() {
+            let _ = multi.poll(&mut [], POLL_INTERVAL);
+        }
+    }
+}
+
+/// Aborts the request if its future is dropped before the answer is complete
+struct Running<'a> {
+    transport: &'a CurlTransport,
+    token: usize,
+    over: bool,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        if !self.over {
+            let _ = self.transport.command(Command::Abort(self.token));
+        }
+    }
+}
+
+enum Received {
+    Status(u32),
+    Data(Vec<u8>),
+}
+
+/// What the future of a request and the curl thread running it tell each other
+#[derive(Default)]
+struct Shared {
+    /// Statuses and body of the answer not given to the sink yet, in the order they came
+    received: VecDeque<Received>,
+    /// Bytes of body in `received`
+    buffered: usize,
+    /// Read ahead from the request body, curl takes it from `body_start`
+    body: Vec<u8>,
+    body_start: usize,
+    /// The whole request body is in `body`
+    body_read: bool,
+    /// curl waits for `body` to be filled, or for `received` to be emptied
+    read_paused: bool,
+    write_paused: bool,
+    /// Bytes of the request body sent and of the answer received
+    sent: u64,
+    received_bytes: u64,
+    /// Status of the answer once it is complete
+    result: Option<Result<u32, SwishError>>,
+    /// Future to wake up once curl has something for it
+    waker: Option<Waker>,
+}
+
+impl Shared {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn finish(&mut self, result: Result<u32, SwishError>) {
+        self.result = Some(result);
+        self.wake();
+    }
+
+    fn body_left(&self) -> usize {
+        self.body.len() - self.body_start
+    }
+
+    fn has_news(&self) -> bool {
+        !self.received.is_empty()
+            || self.result.is_some()
+            || self.read_paused
+            || (!self.body_read && self.body_left() < BODY_BUFFER / 2)
+    }
+}
+
+/// Read ahead from `reader` what curl has taken from `shared`, returns whether curl waits for it.
+/// The lock is not held while reading, the curl thread runs the other requests meanwhile
+fn fill_body(shared: &Mutex<Shared>, reader: &mut (dyn Read + Send)) -> Result<bool, SwishError> {
+    let wanted = {
+        let shared = shared.lock().unwrap();
+        if shared.body_read || shared.body_left() >= BODY_BUFFER / 2 {
+            return Ok(false);
+        }
+        BODY_BUFFER - shared.body_left()
+    };
+    let mut data = Vec::with_capacity(wanted);
+    reader.take(wanted as u64).read_to_end(&mut data)?;
+
+    let mut shared = shared.lock().unwrap();
+    let start = shared.body_start;
+    shared.body.drain(..start);
+    shared.body_start = 0;
+    shared.body.extend_from_slice(&data);
+    shared.body_read = data.len() < wanted;
+    Ok(std::mem::take(&mut shared.read_paused))
+}
+
+/// Handler of the curl handle of a request, it only moves data in and out of `Shared`
+#[derive(Default)]
+struct Exchange(Arc<Mutex<Shared>>);
+
+impl Handler for Exchange {
+    fn header(&mut self, line: &[u8]) -> bool {
+        if let Some(status) = status_line(line) {
+            let mut shared = self.0.lock().unwrap();
+            shared.received.push_back(Received::Status(status));
+            shared.wake();
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
+        let mut shared = self.0.lock().unwrap();
+        // curl hands the same data again once resumed
+        if shared.buffered >= RECEIVE_BUFFER {
+            shared.write_paused = true;
+            return Err(WriteError::Pause);
+        }
+        shared.buffered += data.len();
+        shared.received.push_back(Received::Data(data.to_vec()));
+        shared.wake();
+        Ok(data.len())
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<usize, ReadError> {
+        let mut shared = self.0.lock().unwrap();
+        let start = shared.body_start;
+        let size = data.len().min(shared.body_left());
+        if size == 0 && !shared.body_read {
+            shared.read_paused = true;
+            shared.wake();
+            return Err(ReadError::Pause);
+        }
+        data[..size].copy_from_slice(&shared.body[start..start + size]);
+        shared.body_start += size;
+        if !shared.body_read && shared.body_left() < BODY_BUFFER / 2 {
+            shared.wake();
+        }
+        Ok(size)
+    }
+
+    fn progress(&mut self, _: f64, received: f64, _: f64, sent: f64) -> bool {
+        let mut shared = self.0.lock().unwrap();
+        shared.sent = sent as u64;
+        shared.received_bytes = received as u64;
+        true
+    }
+}
+
+/// Status of an `HTTP/1.1 206 Partial Content` header line, None for any other header
+fn status_line(line: &[u8]) -> Option<u32> {
+    if !line.starts_with(b"HTTP/") {
+        return None;
+    }
+    let status = line.split(|byte| *byte == b' ').nth(1)?;
+    std::str::from_utf8(status).ok()?.parse().ok()
+}
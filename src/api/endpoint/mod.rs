@@ -0,0 +1,48 @@
+/// Where Swish sends its requests.
+///
+/// Everything is derived from a single base url: the API lives under `{base_url}/api`,
+/// download links under `{base_url}/d/` and the upload / download hosts returned by the
+/// server are reached with the same scheme as the base url.
+/// Pointing it to a local server (e.g. `http://127.0.0.1:8080`) lets Swish run without Infomaniak.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub base_url: String,
+}
+
+pub const DEFAULT_BASE_URL: &str = "https://www.swisstransfer.com";
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::new(DEFAULT_BASE_URL)
+    }
+}
+
+impl Endpoint {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `api("containers")` -> `https://www.swisstransfer.com/api/containers`
+    pub fn api(&self, path: &str) -> String {
+        format!("{}/api/{}", self.base_url, path)
+    }
+
+    /// Same as `api` but on one of the upload / download hosts handed out by the server
+    pub fn host_api(&self, host: &str, path: &str) -> String {
+        format!("{}://{}/api/{}", self.scheme(), host, path)
+    }
+
+    /// Public link shared with the recipients of a transfer
+    pub fn download_link(&self, link_uuid: &str) -> String {
+        format!("{}/d/{}", self.base_url, link_uuid)
+    }
+
+    fn scheme(&self) -> &str {
+        match self.base_url.split_once("://") {
+            Some((scheme, _)) => scheme,
+            None => "https",
+        }
+    }
+}
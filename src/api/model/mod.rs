@@ -0,0 +1,183 @@
+//! Payloads exchanged with the SwissTransfer API.
+//!
+//! Only the fields Swish uses are modelled, the server sends more and they are ignored.
+//! Fields that are only informative are optional so a change on the server side does not
+//! prevent downloads.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_path_to_error::Segment;
+
+use crate::errors::SwishError;
+
+/// Body of `POST /api/containers`, declaring a transfer before its files are uploaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerRequest {
+    /// Number of days the transfer is available
+    pub duration: u64,
+    pub author_email: String,
+    pub password: String,
+    pub message: String,
+    /// Size of all the files together, in bytes
+    pub size_of_upload: u64,
+    pub number_of_download: u16,
+    pub number_of_file: usize,
+    pub lang: String,
+    pub recaptcha: String,
+    /// `Vec<ContainerFile>` encoded as a JSON string
+    pub files: String,
+    /// Emails encoded as a JSON string
+    pub recipients_emails: String,
+}
+
+/// A file declared in `ContainerRequest::files`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerFile {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Answer to `POST /api/containers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerResponse {
+    pub container: Container,
+    /// UUIDs of the files, in the order they were declared
+    #[serde(rename = "filesUUID")]
+    pub files_uuid: Vec<String>,
+    /// Host the chunks are sent to
+    pub upload_host: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Container {
+    #[serde(rename = "UUID")]
+    pub uuid: String,
+    #[serde(default)]
+    pub duration: Option<u64>,
+    #[serde(default)]
+    pub expired_date: Option<String>,
+}
+
+/// Body of `POST /api/uploadComplete`, once every chunk has been sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadCompleteRequest {
+    #[serde(rename = "UUID")]
+    pub uuid: String,
+    pub lang: String,
+}
+
+/// Answer to `POST /api/uploadComplete`, a link for each way the transfer is shared
+pub type UploadCompleteResponse = Vec<UploadedLink>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadedLink {
+    #[serde(rename = "linkUUID")]
+    pub link_uuid: String,
+}
+
+/// Only the message of an answer to `GET /api/links/{link}`, set when the transfer can not be
+/// listed yet (password needed, virus check running...)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinksStatus {
+    pub data: LinksMessage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinksMessage {
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Answer to `GET /api/links/{link}`, what a transfer holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinksResponse {
+    pub data: Link,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Link {
+    /// Host the files are downloaded from
+    pub download_host: String,
+    #[serde(rename = "linkUUID")]
+    pub link_uuid: String,
+    pub container: LinkContainer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkContainer {
+    #[serde(rename = "UUID")]
+    pub uuid: String,
+    pub files: Vec<FileEntry>,
+}
+
+/// A file of a transfer as listed by `GET /api/links/{link}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+    #[serde(rename = "UUID")]
+    pub uuid: String,
+    pub file_name: String,
+    pub file_size_in_bytes: u64,
+    #[serde(default)]
+    pub created_date: Option<String>,
+    #[serde(default)]
+    pub expired_date: Option<String>,
+    #[serde(default)]
+    pub deleted_date: Option<String>,
+    #[serde(default)]
+    pub download_counter: Option<u64>,
+    /// State of the virus scan
+    #[serde(default, rename = "eVirus")]
+    pub e_virus: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+/// Body of `POST /api/generateDownloadToken`, to download a file of a protected transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadTokenRequest {
+    pub password: String,
+    #[serde(rename = "containerUUID")]
+    pub container_uuid: String,
+    #[serde(rename = "fileUUID")]
+    pub file_uuid: String,
+}
+
+/// Answer to `POST /api/generateDownloadToken`, a JSON string
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DownloadToken(pub String);
+
+/// Parse a server response. Invalid JSON is reported as `SwishError::InvalidJson`, valid JSON
+/// missing a field, or with a field of another type, as `SwishError::MissingField`
+pub fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, SwishError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|error| {
+        if !error.inner().is_data() {
+            return SwishError::from(error.into_inner());
+        }
+
+        // The path leads to the field itself, or to the object it is missing from
+        let mut path = String::new();
+        for segment in error.path().iter() {
+            match segment {
+                Segment::Seq { index } => path.push_str(&format!("/{}", index)),
+                Segment::Map { key } => path.push_str(&format!("/{}", key)),
+                Segment::Enum { variant } => path.push_str(&format!("/{}", variant)),
+                Segment::Unknown => path.push_str("/?"),
+            }
+        }
+        let message = error.inner().to_string();
+        if let Some(field) = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next())
+        {
+            path.push_str(&format!("/{}", field));
+        }
+        SwishError::MissingField { path }
+    })
+}
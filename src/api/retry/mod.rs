@@ -0,0 +1,60 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How often and how patiently a failed request is sent again
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of times a request is sent before giving up, the first one included
+    pub max_attempts: u32,
+    /// Wait before the second attempt, it doubles after each failure
+    pub base_delay: Duration,
+    /// The wait never grows past this
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before sending the request again after `attempt` failed ones.
+    /// Half of it is random so parallel uploads failing together do not retry together
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(jitter())
+    }
+}
+
+/// The server is overloaded or broken for now, asking again later may work
+pub fn is_retryable_status(response_code: u32) -> bool {
+    response_code == 429 || (500..600).contains(&response_code)
+}
+
+/// The connection failed, as opposed to a local error such as an unreadable file
+pub fn is_retryable_error(error: &curl::Error) -> bool {
+    error.is_couldnt_resolve_host()
+        || error.is_couldnt_connect()
+        || error.is_operation_timedout()
+        || error.is_partial_file()
+        || error.is_got_nothing()
+        || error.is_send_error()
+        || error.is_recv_error()
+        || error.is_ssl_connect_error()
+        || error.is_http2_error()
+        || error.is_http2_stream_error()
+}
+
+/// A random number between 0 and 1, every `RandomState` is seeded differently
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
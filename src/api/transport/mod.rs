@@ -0,0 +1,205 @@
+//! How requests reach the server.
+//!
+//! Everything Swish sends goes through a [`Transport`], `CurlTransport` unless told otherwise.
+//! Giving another one to `Swissfiles` lets it use another HTTP client, wrap the default one
+//! (logging, authentication, metrics...) or answer from memory without opening any socket.
+//! Requests are futures run on tokio, dropping one aborts its request.
+
+use std::future::Future;
+use std::io::{self, Read};
+use std::pin::Pin;
+
+use crate::errors::SwishError;
+
+/// Headers SwissTransfer expects on every request
+pub const DEFAULT_HEADERS: &[&str; 3] = &[
+    "User-Agent: swisstransfer-webext/1.0",
+    "Cookie: webext=1",
+    "Referer: swish/1.0.1",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+pub enum Body<'a> {
+    /// Nothing is sent
+    Empty,
+    /// Sent as is
+    Bytes(Vec<u8>),
+    /// Read while it is sent, `reader` must give exactly `size` bytes
+    Stream {
+        reader: &'a mut (dyn Read + Send),
+        size: u64,
+    },
+}
+
+impl Body<'_> {
+    pub fn size(&self) -> u64 {
+        match self {
+            Body::Empty => 0,
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::Stream { size, .. } => *size,
+        }
+    }
+}
+
+pub struct Request<'a> {
+    pub method: Method,
+    pub url: String,
+    /// Sent as given, `Name: value`
+    pub headers: Vec<String>,
+    pub body: Body<'a>,
+}
+
+impl<'a> Request<'a> {
+    /// GET `url` with the headers SwissTransfer expects
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: DEFAULT_HEADERS
+                .iter()
+                .map(|header| header.to_string())
+                .collect(),
+            body: Body::Empty,
+        }
+    }
+
+    /// POST `body` to `url` as JSON, with the headers SwissTransfer expects
+    pub fn post(url: &str, body: Body<'a>) -> Self {
+        let mut request = Self::get(url);
+        request.method = Method::Post;
+        request.body = body;
+        request
+            .header("Content-Type: application/json")
+            .header("Accept: application/json")
+    }
+
+    /// Add a header such as `Range: bytes=0-99`
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.headers.push(header.into());
+        self
+    }
+}
+
+/// Receives the answer of the server as it comes
+pub trait Sink: Send {
+    /// The server answered with `status`, nothing of the body has been written yet.
+    /// Interim answers such as `100 Continue` are reported too, the last one is the answer.
+    /// Returning false aborts the request
+    fn status(&mut self, status: u32) -> bool {
+        let _ = status;
+        true
+    }
+
+    /// Some more of the body, an error aborts the request
+    fn write(&mut self, data: &[u8]) -> io::Result<()>;
+}
+
+impl Sink for Vec<u8> {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// What the methods of a [`Transport`] return, boxed so that it can be used as `dyn Transport`
+pub type Sending<'a, T> = Pin<Box<dyn Future<Output = Result<T, SwishError>> + Send + 'a>>;
+
+/// Sends requests and hands back what the server answers
+pub trait Transport: Send + Sync {
+    /// Send `request`, the body of the answer is written to `sink`.
+    /// `progress` is given the bytes of the request body sent and of the answer received so far,
+    /// it is called regularly even when nothing moves and returning false aborts the request.
+    /// Returns the status of the answer whatever it is, only failing to get one is an error
+    fn send<'a>(
+        &'a self,
+        request: Request<'a>,
+        sink: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32>;
+
+    /// GET `url`, the body of a successful answer must be text
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        additional_headers: Option<Vec<String>>,
+    ) -> Sending<'a, String> {
+        Box::pin(async move {
+            let additional_headers = additional_headers.unwrap_or_default();
+            log::debug!(
+                "Sending get request to: {} \n with headers {}",
+                url,
+                additional_headers.join("\n")
+            );
+
+            let mut request = Request::get(url);
+            request.headers.extend(additional_headers);
+            let mut data = Vec::new();
+            let status = self.send(request, &mut data, &mut |_, _| true).await?;
+
+            log::debug!(
+                "Response: {} - {:?}",
+                status,
+                String::from_utf8_lossy(&data)
+            );
+
+            match status {
+                200 => String::from_utf8(data).map_err(|error| SwishError::UnexpectedResponse {
+                    status: 200,
+                    body: String::from_utf8_lossy(error.as_bytes()).to_string(),
+                }),
+                404 => Err(SwishError::NotFound {
+                    url: url.to_string(),
+                }),
+                status => Err(SwishError::UnexpectedResponse {
+                    status,
+                    body: String::from_utf8_lossy(&data).to_string(),
+                }),
+            }
+        })
+    }
+
+    /// POST `body` as JSON, an answer of 400 or more is retried 3 times
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        body: Vec<u8>,
+        additional_headers: Option<Vec<String>>,
+    ) -> Sending<'a, Vec<u8>> {
+        Box::pin(async move {
+            log::debug!("Sending post request to: {}", url);
+            log::debug!("Body: {}", String::from_utf8_lossy(&body));
+            let mut retries = 0;
+
+            loop {
+                let mut request = Request::post(url, Body::Bytes(body.clone()));
+                request
+                    .headers
+                    .extend(additional_headers.iter().flatten().cloned());
+                let mut data = Vec::new();
+                let status = self.send(request, &mut data, &mut |_, _| true).await?;
+                log::debug!(
+                    "Response: {} - {:?}",
+                    status,
+                    String::from_utf8_lossy(&data)
+                );
+
+                if status < 400 {
+                    return Ok(data);
+                } else if retries >= 3 {
+                    return Err(SwishError::UnexpectedResponse {
+                        status,
+                        body: String::from_utf8_lossy(&data).to_string(),
+                    });
+                } else {
+                    log::warn!("Request failed, retrying... ({})", retries);
+                    retries += 1;
+                }
+            }
+        })
+    }
+}
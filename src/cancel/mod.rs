@@ -0,0 +1,63 @@
+//! Stopping a transfer from outside of it.
+//!
+//! Whoever wants a transfer to stop, a Ctrl-C handler for instance, cancels the [`CancelToken`]
+//! of its `Swissfiles`. Transfers check it and stop so they can clean up behind them.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::errors::SwishError;
+
+/// How often a waiting transfer checks whether it was cancelled
+const SLEEP_STEP: Duration = Duration::from_millis(50);
+
+/// Cancelling a token cancels its clones too
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Arc<CancelToken>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token cancelled along with this one, that can also be cancelled on its own
+    pub fn child(&self) -> Self {
+        Self {
+            cancelled: Default::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    /// Ask the transfers holding this token to stop, it can not be undone.
+    /// Only sets a flag, so it can be called from a signal handler
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Wait for `duration`, or fail with `SwishError::Interrupted` as soon as the token is cancelled
+    pub async fn sleep(&self, duration: Duration) -> Result<(), SwishError> {
+        let end = Instant::now() + duration;
+        loop {
+            if self.is_cancelled() {
+                return Err(SwishError::Interrupted);
+            }
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            tokio::time::sleep(left.min(SLEEP_STEP)).await;
+        }
+    }
+}
@@ -13,6 +13,19 @@ pub enum SwishError {
     PasswordRequired,
     InvalidPassword,
     DownloadNumberExceeded,
+    InvalidPattern { pattern: String, error: String },
+    NothingToUpload { path: String },
+    NothingToDownload,
+    InvalidState { path: String, reason: String },
+    InvalidChunkSize { size: usize, min: usize, max: usize },
+    Interrupted,
+    FileExists { path: String },
+    DownloadsFailed { errors: Vec<(String, SwishError)> },
+    IncompleteDownload { name: String, received: u64, expected: u64 },
+    ChunkUploadFailed { file: String, chunk: usize, attempts: u32, reason: String },
+    UnsafeFileName { name: String, reason: &'static str },
+    MissingField { path: String },
+    UnexpectedResponse { status: u32, body: String },
 }
 
 impl fmt::Display for SwishError {
@@ -27,10 +40,123 @@ impl fmt::Display for SwishError {
             SwishError::PasswordRequired => write!(f, "A password is required to download this file please provide it using the -p flag or --password flag"),
             SwishError::InvalidPassword => write!(f, "The password provided is incorrect"),
             SwishError::DownloadNumberExceeded => write!(f, "The number of download has been exceeded"),
+            SwishError::InvalidPattern { pattern, error } => write!(f, "Invalid pattern {}: {}", pattern, error),
+            SwishError::NothingToUpload { path } => write!(f, "Nothing to upload in {}, every file has been filtered out", path),
+            SwishError::NothingToDownload => write!(f, "Nothing to download, no file of the transfer is selected"),
+            SwishError::InvalidState { path, reason } => write!(f, "Can not resume the upload from {}: {}", path, reason),
+            SwishError::InvalidChunkSize { size, min, max } => write!(f, "Invalid chunk size {} bytes, it must be between {} and {} bytes", size, min, max),
+            SwishError::Interrupted => write!(f, "Interrupted"),
+            SwishError::FileExists { path } => write!(f, "{} already exists, see --on-conflict", path),
+            SwishError::DownloadsFailed { errors } => {
+                write!(f, "{} files could not be downloaded:", errors.len())?;
+                for (name, error) in errors {
+                    write!(f, "\n  {}: {}", name, error)?;
+                }
+                Ok(())
+            }
+            SwishError::IncompleteDownload { name, received, expected } => write!(f, "Only {} of the {} bytes of {} were downloaded, run again with --continue to get the rest", received, expected, name),
+            SwishError::ChunkUploadFailed { file, chunk, attempts, reason } => write!(f, "Chunk {} of {} could not be uploaded after {} attempt(s): {}", chunk, file, attempts, reason),
+            SwishError::UnsafeFileName { name, reason } => write!(f, "Refusing to download {:?}: {}", name, reason),
+            SwishError::MissingField { path } => write!(f, "The server response has no valid {}, its API may have changed", path),
+            SwishError::UnexpectedResponse { status, body } => {
+                // Error pages can be long, the start is enough to tell what happened
+                let excerpt: String = body.chars().take(200).collect();
+                write!(f, "The server answered with {}: {}", status, excerpt.trim())?;
+                if excerpt.len() < body.len() {
+                    write!(f, "...")?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+impl SwishError {
+    /// Identifier of the kind of error, scripts can rely on it as it never changes
+    pub fn code(&self) -> &'static str {
+        match self {
+            SwishError::InvalidUrl { .. } => "invalid_url",
+            SwishError::InvalidJson { .. } => "invalid_json",
+            SwishError::InvalidResponse { .. } => "invalid_response",
+            SwishError::CurlError { .. } => "network_error",
+            SwishError::FileError { .. } => "file_error",
+            SwishError::NotFound { .. } => "not_found",
+            SwishError::PasswordRequired => "password_required",
+            SwishError::InvalidPassword => "invalid_password",
+            SwishError::DownloadNumberExceeded => "download_number_exceeded",
+            SwishError::InvalidPattern { .. } => "invalid_pattern",
+            SwishError::NothingToUpload { .. } => "nothing_to_upload",
+            SwishError::NothingToDownload => "nothing_to_download",
+            SwishError::InvalidState { .. } => "invalid_state",
+            SwishError::InvalidChunkSize { .. } => "invalid_chunk_size",
+            SwishError::Interrupted => "interrupted",
+            SwishError::FileExists { .. } => "file_exists",
+            SwishError::DownloadsFailed { .. } => "downloads_failed",
+            SwishError::IncompleteDownload { .. } => "incomplete_download",
+            SwishError::ChunkUploadFailed { .. } => "chunk_upload_failed",
+            SwishError::UnsafeFileName { .. } => "unsafe_file_name",
+            SwishError::MissingField { .. } => "missing_field",
+            SwishError::UnexpectedResponse { .. } => "unexpected_response",
+        }
+    }
+
+    /// Status the process exits with, distinct for each kind of error and documented in the README.
+    /// 2 is left to invalid arguments, as clap uses it, and 130 is the usual status after Ctrl-C
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            // What was asked makes no sense
+            SwishError::InvalidUrl { .. } => 3,
+            SwishError::InvalidPattern { .. } => 4,
+            SwishError::InvalidChunkSize { .. } => 5,
+            SwishError::NothingToUpload { .. } => 6,
+            SwishError::NothingToDownload => 7,
+            SwishError::InvalidState { .. } => 8,
+            // The transfer can not be accessed
+            SwishError::PasswordRequired => 10,
+            SwishError::InvalidPassword => 11,
+            SwishError::NotFound { .. } => 12,
+            SwishError::DownloadNumberExceeded => 13,
+            // Talking to the server failed
+            SwishError::CurlError { .. } => 20,
+            SwishError::InvalidResponse { .. } => 21,
+            SwishError::InvalidJson { .. } => 22,
+            SwishError::ChunkUploadFailed { .. } => 23,
+            SwishError::MissingField { .. } => 24,
+            SwishError::UnexpectedResponse { .. } => 25,
+            // Reading or writing files failed
+            SwishError::FileError { .. } => 30,
+            SwishError::FileExists { .. } => 31,
+            SwishError::UnsafeFileName { .. } => 32,
+            SwishError::IncompleteDownload { .. } => 33,
+            SwishError::DownloadsFailed { .. } => 34,
+            SwishError::Interrupted => 130,
+        }
+    }
+
+    /// The error as `{"error": {"code": ..., "message": ...}}`, with the error of each file
+    /// under `files` when several downloads failed
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut error = serde_json::json!({
+            "code": self.code(),
+            "exit_code": self.exit_code(),
+            "message": self.to_string(),
+        });
+        if let SwishError::DownloadsFailed { errors } = self {
+            error["files"] = errors
+                .iter()
+                .map(|(name, error)| {
+                    serde_json::json!({
+                        "name": name,
+                        "code": error.code(),
+                        "message": error.to_string(),
+                    })
+                })
+                .collect();
+        }
+        serde_json::json!({ "error": error })
+    }
+}
+
 impl Error for SwishError {}
 
 impl From<CurlError> for SwishError {
@@ -0,0 +1,680 @@
+//! In-process stand-in for the SwissTransfer API, only built with the `fake-server` feature.
+//!
+//! It keeps every transfer in memory and speaks just enough of the protocol for Swish to
+//! upload and download files without reaching Infomaniak:
+//!
+//! ```no_run
+//! use swish::fakeserver::{FakeServer, Failure};
+//!
+//! let server = FakeServer::start();
+//! let endpoint = server.endpoint();
+//! server.inject(Failure::VirusCheckPending(2));
+//! // ... use `endpoint` with `Swissfiles::new_localfiles` / `Swissfiles::new_remotefiles`
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use base64::prelude::*;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::api::endpoint::Endpoint;
+
+/// How long a stalled request is held before its connection is dropped, longer than a client waits
+const STALL_TIME: Duration = Duration::from_secs(120);
+
+/// Failures the server can be asked to simulate
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// Every password sent to `/api/links/{id}` is considered wrong
+    WrongPassword,
+    /// The next `n` calls to `/api/links/{id}` answer that the virus check is still running
+    VirusCheckPending(u32),
+    /// Every download answers with a 500
+    DownloadError,
+    /// Downloads of the file with this name answer with a 404
+    MissingFile(String),
+    /// The next `n` requests, whatever they are, answer with a 429
+    Throttle(u32),
+    /// Chunk uploads answer with a 500 once `after` more of them have been accepted
+    UploadError { after: u32 },
+    /// The next `n` chunk uploads answer with a 503
+    ChunkUnavailable(u32),
+    /// The next `n` chunk uploads are read but never answered, as if the server hung
+    ChunkStall(u32),
+    /// Downloads ignore the `Range` header and always send the whole file
+    IgnoreRange,
+    /// The next download only sends its first `after` bytes, as if the connection dropped
+    DownloadCut { after: usize },
+    /// The next request whose path starts with `path` is handled as usual but answered with
+    /// `status` and `body` instead, e.g. truncated JSON or an HTML error page
+    RawResponse {
+        path: String,
+        status: u16,
+        body: String,
+    },
+}
+
+/// A file as the server sees it
+#[derive(Debug, Clone)]
+pub struct FakeFile {
+    pub uuid: String,
+    pub name: String,
+    pub size: u64,
+    pub mime_type: String,
+    pub download_counter: u64,
+    /// Chunks received so far, by chunk index
+    pub chunks: BTreeMap<usize, Vec<u8>>,
+    /// Index of the chunk flagged as the last one, if any
+    pub last_chunk: Option<usize>,
+    /// Number of chunk uploads received for this file, rejected ones included
+    pub chunk_requests: u64,
+    /// Bytes of the file sent by downloads so far
+    pub bytes_served: u64,
+}
+
+impl FakeFile {
+    /// Content of the file, i.e. the received chunks put back together
+    pub fn content(&self) -> Vec<u8> {
+        self.chunks.values().flatten().copied().collect()
+    }
+}
+
+/// A transfer (container) as the server sees it
+#[derive(Debug, Clone)]
+pub struct FakeTransfer {
+    pub container_uuid: String,
+    pub link_uuid: Option<String>,
+    pub password: String,
+    pub message: String,
+    pub duration: u64,
+    pub number_of_download: u64,
+    pub files: Vec<FakeFile>,
+}
+
+#[derive(Default)]
+struct State {
+    host: String,
+    next_id: u64,
+    transfers: Vec<FakeTransfer>,
+    failures: Vec<Failure>,
+}
+
+impl State {
+    fn new_uuid(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:08x}-0000-4000-8000-{:012x}", self.next_id, self.next_id)
+    }
+
+    fn has_failure(&self, failure: &Failure) -> bool {
+        self.failures.contains(failure)
+    }
+
+    /// Consume one occurrence of a counted failure, returns true if it was active
+    fn take_counted(&mut self, matches: fn(&Failure) -> bool) -> bool {
+        let position = self.failures.iter().position(matches);
+        match position {
+            Some(position) => {
+                match &mut self.failures[position] {
+                    Failure::VirusCheckPending(n)
+                    | Failure::Throttle(n)
+                    | Failure::ChunkUnavailable(n)
+                    | Failure::ChunkStall(n)
+                        if *n > 1 =>
+                    {
+                        *n -= 1
+                    }
+                    _ => {
+                        self.failures.remove(position);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct FakeServer {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FakeServer {
+    /// Start a server on a random local port
+    pub fn start() -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let host = server.server_addr().to_ip().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State {
+            host,
+            ..State::default()
+        }));
+
+        let handle = {
+            let server = server.clone();
+            let state = state.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let state = state.clone();
+                    std::thread::spawn(move || handle_request(&state, request));
+                }
+            })
+        };
+
+        Self {
+            server,
+            state,
+            handle: Some(handle),
+        }
+    }
+
+    /// Endpoint to hand to Swish so it talks to this server
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::new(&format!("http://{}", self.state.lock().unwrap().host))
+    }
+
+    pub fn inject(&self, failure: Failure) {
+        self.state.lock().unwrap().failures.push(failure);
+    }
+
+    /// Remove every injected failure
+    pub fn clear_failures(&self) {
+        self.state.lock().unwrap().failures.clear();
+    }
+
+    /// Snapshot of every transfer created so far
+    pub fn transfers(&self) -> Vec<FakeTransfer> {
+        self.state.lock().unwrap().transfers.clone()
+    }
+
+    /// Snapshot of the transfer behind a download link
+    pub fn transfer(&self, download_link: &str) -> Option<FakeTransfer> {
+        let link_uuid = download_link.split('/').next_back()?;
+        self.transfers()
+            .into_iter()
+            .find(|transfer| transfer.link_uuid.as_deref() == Some(link_uuid))
+    }
+
+    /// Change the name the server gives to a file of a transfer, e.g. to something hostile
+    pub fn rename_file(&self, download_link: &str, name: &str, new_name: &str) {
+        let link_uuid = download_link.split('/').next_back().unwrap();
+        let mut state = self.state.lock().unwrap();
+        let transfer = find_transfer_mut(&mut state, link_uuid).unwrap();
+        let file = transfer.files.iter_mut().find(|file| file.name == name).unwrap();
+        file.name = new_name.to_string();
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Reply {
+    status: u16,
+    body: Vec<u8>,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    /// Never answer, see `Failure::ChunkStall`
+    stall: bool,
+}
+
+impl Reply {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            body: value.to_string().into_bytes(),
+            content_type: "application/json",
+            headers: Vec::new(),
+            stall: false,
+        }
+    }
+
+    fn bytes(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            body,
+            content_type: "application/octet-stream",
+            headers: Vec::new(),
+            stall: false,
+        }
+    }
+
+    fn status(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: message.as_bytes().to_vec(),
+            content_type: "text/html",
+            headers: Vec::new(),
+            stall: false,
+        }
+    }
+
+    fn stall() -> Self {
+        Self {
+            stall: true,
+            ..Self::status(504, "Gateway Timeout")
+        }
+    }
+}
+
+fn handle_request(state: &Mutex<State>, mut request: Request) {
+    let mut body = Vec::new();
+    let reply = match request.as_reader().read_to_end(&mut body) {
+        Ok(_) => route(state, &request, &body),
+        Err(_) => Reply::status(400, "Unreadable body"),
+    };
+    if reply.stall {
+        std::thread::sleep(STALL_TIME);
+        return;
+    }
+
+    let header = Header::from_bytes("Content-Type", reply.content_type).unwrap();
+    let mut response = Response::from_data(reply.body)
+        .with_status_code(reply.status)
+        .with_header(header);
+    for (field, value) in reply.headers {
+        response.add_header(Header::from_bytes(field, value).unwrap());
+    }
+    let _ = request.respond(response);
+}
+
+fn route(state: &Mutex<State>, request: &Request, body: &[u8]) -> Reply {
+    let mut state = state.lock().unwrap();
+
+    if state.take_counted(|failure| matches!(failure, Failure::Throttle(_))) {
+        return Reply::status(429, "<html><body>Too Many Requests</body></html>");
+    }
+
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (request.url(), ""),
+    };
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    let reply = match (request.method(), segments.as_slice()) {
+        (Method::Post, ["api", "containers"]) => create_container(&mut state, body),
+        (Method::Post, ["api", "uploadChunk", container, file, index, last]) => {
+            upload_chunk(&mut state, container, file, index, last, body)
+        }
+        (Method::Post, ["api", "uploadComplete"]) => upload_complete(&mut state, body),
+        (Method::Get, ["api", "links", link]) => {
+            let authorization = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Authorization"))
+                .map(|header| header.value.as_str().to_string());
+            links(&mut state, link, authorization)
+        }
+        (Method::Post, ["api", "generateDownloadToken"]) => generate_download_token(&state, body),
+        (Method::Get, ["api", "download", link, file]) => {
+            let range = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Range"))
+                .map(|header| header.value.as_str().to_string());
+            download(&mut state, link, file, query, range)
+        }
+        _ => Reply::status(404, "Not Found"),
+    };
+
+    let raw = state.failures.iter().position(|failure| match failure {
+        Failure::RawResponse { path: prefix, .. } => path.starts_with(prefix.as_str()),
+        _ => false,
+    });
+    match raw.map(|position| state.failures.remove(position)) {
+        Some(Failure::RawResponse { status, body, .. }) => Reply::status(status, &body),
+        _ => reply,
+    }
+}
+
+fn create_container(state: &mut State, body: &[u8]) -> Reply {
+    let payload: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(payload) => payload,
+        Err(_) => return Reply::status(400, "Invalid payload"),
+    };
+    // the file list is sent as a JSON string
+    let files: Vec<serde_json::Value> = payload["files"]
+        .as_str()
+        .and_then(|files| serde_json::from_str(files).ok())
+        .unwrap_or_default();
+
+    let container_uuid = state.new_uuid();
+    let mut fake_files = Vec::new();
+    for file in &files {
+        let name = file["name"].as_str().unwrap_or_default().to_string();
+        fake_files.push(FakeFile {
+            uuid: state.new_uuid(),
+            mime_type: mime_type(&name).to_string(),
+            name,
+            size: file["size"].as_u64().unwrap_or_default(),
+            download_counter: 0,
+            chunks: BTreeMap::new(),
+            last_chunk: None,
+            chunk_requests: 0,
+            bytes_served: 0,
+        });
+    }
+
+    let files_uuid: Vec<&str> = fake_files.iter().map(|file| file.uuid.as_str()).collect();
+    let reply = Reply::json(
+        200,
+        json!({
+            "container": {
+                "UUID": container_uuid,
+                "duration": payload["duration"],
+                "numberOfFile": fake_files.len(),
+                "expiredDate": format!(
+                    "2024-01-{:02} 00:00:00",
+                    1 + payload["duration"].as_u64().unwrap_or(30)
+                ),
+            },
+            "filesUUID": files_uuid,
+            "uploadHost": state.host,
+        }),
+    );
+
+    state.transfers.push(FakeTransfer {
+        container_uuid,
+        link_uuid: None,
+        password: payload["password"].as_str().unwrap_or_default().to_string(),
+        message: payload["message"].as_str().unwrap_or_default().to_string(),
+        duration: payload["duration"].as_u64().unwrap_or(30),
+        number_of_download: payload["numberOfDownload"].as_u64().unwrap_or(250),
+        files: fake_files,
+    });
+
+    reply
+}
+
+fn upload_chunk(
+    state: &mut State,
+    container_uuid: &str,
+    file_uuid: &str,
+    index: &str,
+    last: &str,
+    body: &[u8],
+) -> Reply {
+    let index: usize = match index.parse() {
+        Ok(index) => index,
+        Err(_) => return Reply::status(400, "Invalid chunk index"),
+    };
+
+    let unavailable = state.take_counted(|failure| matches!(failure, Failure::ChunkUnavailable(_)));
+    let stalled = state.take_counted(|failure| matches!(failure, Failure::ChunkStall(_)));
+    let mut rejected = false;
+    for failure in state.failures.iter_mut() {
+        if let Failure::UploadError { after } = failure {
+            match after {
+                0 => rejected = true,
+                _ => *after -= 1,
+            }
+        }
+    }
+
+    let file = state
+        .transfers
+        .iter_mut()
+        .filter(|transfer| transfer.container_uuid == container_uuid)
+        .flat_map(|transfer| transfer.files.iter_mut())
+        .find(|file| file.uuid == file_uuid);
+
+    match file {
+        Some(file) if rejected => {
+            file.chunk_requests += 1;
+            Reply::status(500, "Internal Server Error")
+        }
+        Some(file) if unavailable => {
+            file.chunk_requests += 1;
+            Reply::status(503, "Service Unavailable")
+        }
+        Some(file) if stalled => {
+            file.chunk_requests += 1;
+            Reply::stall()
+        }
+        // The last chunk completes the file, everything before it must be there already
+        Some(file) if last == "1" && (0..index).any(|i| !file.chunks.contains_key(&i)) => {
+            file.chunk_requests += 1;
+            Reply::status(400, "Chunks are missing before the last one")
+        }
+        Some(file) => {
+            file.chunk_requests += 1;
+            file.chunks.insert(index, body.to_vec());
+            if last == "1" {
+                file.last_chunk = Some(index);
+            }
+            Reply::json(200, json!({ "result": "success" }))
+        }
+        None => Reply::status(404, "Unknown container or file"),
+    }
+}
+
+fn upload_complete(state: &mut State, body: &[u8]) -> Reply {
+    let payload: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let container_uuid = payload["UUID"].as_str().unwrap_or_default();
+
+    let position = state
+        .transfers
+        .iter()
+        .position(|transfer| transfer.container_uuid == container_uuid);
+    match position {
+        Some(position) => {
+            let link_uuid = state.new_uuid();
+            state.transfers[position].link_uuid = Some(link_uuid.clone());
+            Reply::json(200, json!([{ "linkUUID": link_uuid }]))
+        }
+        None => Reply::status(404, "Unknown container"),
+    }
+}
+
+fn links(state: &mut State, link_uuid: &str, authorization: Option<String>) -> Reply {
+    let transfer = match find_transfer(state, link_uuid) {
+        Some(transfer) => transfer.clone(),
+        None => return Reply::status(404, "Not Found"),
+    };
+
+    if !transfer.password.is_empty() || state.has_failure(&Failure::WrongPassword) {
+        let password = authorization
+            .and_then(|authorization| BASE64_STANDARD.decode(authorization.trim()).ok())
+            .map(|password| String::from_utf8_lossy(&password).to_string());
+        match password {
+            None => return message("Transfer need a password"),
+            Some(_) if state.has_failure(&Failure::WrongPassword) => {
+                return message("The password is wrong")
+            }
+            Some(password) if password != transfer.password => {
+                return message("The password is wrong")
+            }
+            Some(_) => (),
+        }
+    }
+
+    if state.take_counted(|failure| matches!(failure, Failure::VirusCheckPending(_))) {
+        return message("All file are not finish to virus check");
+    }
+
+    let files: Vec<serde_json::Value> = transfer
+        .files
+        .iter()
+        .map(|file| {
+            json!({
+                "UUID": file.uuid,
+                "fileName": file.name,
+                "fileSizeInBytes": file.size,
+                "createdDate": "2024-01-01 00:00:00",
+                "expiredDate": "2024-01-31 00:00:00",
+                "deletedDate": null,
+                "downloadCounter": file.download_counter,
+                "eVirus": "CLEAN",
+                "mimeType": file.mime_type,
+            })
+        })
+        .collect();
+
+    Reply::json(
+        200,
+        json!({
+            "data": {
+                "linkUUID": link_uuid,
+                "downloadHost": state.host,
+                "container": {
+                    "UUID": transfer.container_uuid,
+                    "duration": transfer.duration,
+                    "message": transfer.message,
+                    "numberOfFile": transfer.files.len(),
+                    "files": files,
+                },
+            }
+        }),
+    )
+}
+
+fn generate_download_token(state: &State, body: &[u8]) -> Reply {
+    let payload: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let container_uuid = payload["containerUUID"].as_str().unwrap_or_default();
+    let file_uuid = payload["fileUUID"].as_str().unwrap_or_default();
+
+    let transfer = state
+        .transfers
+        .iter()
+        .find(|transfer| transfer.container_uuid == container_uuid);
+    match transfer {
+        Some(transfer) if payload["password"].as_str() == Some(transfer.password.as_str()) => {
+            Reply::json(200, json!(download_token(container_uuid, file_uuid)))
+        }
+        Some(_) => Reply::status(401, "Wrong password"),
+        None => Reply::status(404, "Unknown container"),
+    }
+}
+
+fn download(
+    state: &mut State,
+    link_uuid: &str,
+    file_uuid: &str,
+    query: &str,
+    range: Option<String>,
+) -> Reply {
+    if state.has_failure(&Failure::DownloadError) {
+        return Reply::status(500, "Internal Server Error");
+    }
+
+    let ignore_range = state.has_failure(&Failure::IgnoreRange);
+    let cut_after = state.failures.iter().find_map(|failure| match failure {
+        Failure::DownloadCut { after } => Some(*after),
+        _ => None,
+    });
+    state
+        .failures
+        .retain(|failure| !matches!(failure, Failure::DownloadCut { .. }));
+
+    let missing: Vec<String> = state
+        .failures
+        .iter()
+        .filter_map(|failure| match failure {
+            Failure::MissingFile(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let transfer = match find_transfer_mut(state, link_uuid) {
+        Some(transfer) => transfer,
+        None => return Reply::status(404, "Not Found"),
+    };
+
+    if !transfer.password.is_empty() {
+        let token = query
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("token="));
+        if token != Some(download_token(&transfer.container_uuid, file_uuid).as_str()) {
+            return Reply::status(401, "Invalid token");
+        }
+    }
+
+    let number_of_download = transfer.number_of_download;
+    let file = match transfer.files.iter_mut().find(|file| file.uuid == file_uuid) {
+        Some(file) if !missing.contains(&file.name) => file,
+        _ => return Reply::status(404, "Not Found"),
+    };
+    // we are not sure what the real service does there but the client expects a 500
+    if file.download_counter >= number_of_download {
+        return Reply::status(500, "Internal Server Error");
+    }
+    file.download_counter += 1;
+
+    // Only single `bytes=N-` and `bytes=N-M` ranges are supported, that is all Swish asks for
+    let content = file.content();
+    let range = range
+        .as_deref()
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| {
+            let start = start.parse::<usize>().ok()?;
+            let end = match end {
+                "" => content.len().saturating_sub(1),
+                end => end.parse::<usize>().ok()?.min(content.len().saturating_sub(1)),
+            };
+            Some((start, end))
+        });
+    let mut reply = match range {
+        Some(_) if ignore_range => Reply::bytes(content),
+        Some((start, end)) if start >= content.len() || start > end => {
+            return Reply::status(416, "Range Not Satisfiable")
+        }
+        Some((start, end)) => {
+            let mut reply = Reply::bytes(content[start..=end].to_vec());
+            reply.status = 206;
+            reply.headers.push((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, content.len()),
+            ));
+            reply
+        }
+        None => Reply::bytes(content),
+    };
+
+    if let Some(after) = cut_after {
+        reply.body.truncate(after);
+    }
+    file.bytes_served += reply.body.len() as u64;
+    reply
+}
+
+fn message(message: &str) -> Reply {
+    Reply::json(200, json!({ "data": { "message": message } }))
+}
+
+fn download_token(container_uuid: &str, file_uuid: &str) -> String {
+    format!("token-{}-{}", container_uuid, file_uuid)
+}
+
+fn find_transfer<'a>(state: &'a State, link_uuid: &str) -> Option<&'a FakeTransfer> {
+    state
+        .transfers
+        .iter()
+        .find(|transfer| transfer.link_uuid.as_deref() == Some(link_uuid))
+}
+
+fn find_transfer_mut<'a>(state: &'a mut State, link_uuid: &str) -> Option<&'a mut FakeTransfer> {
+    state
+        .transfers
+        .iter_mut()
+        .find(|transfer| transfer.link_uuid.as_deref() == Some(link_uuid))
+}
+
+fn mime_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
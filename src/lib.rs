@@ -1,3 +1,6 @@
 pub mod api;
+pub mod cancel;
 pub mod errors;
+#[cfg(feature = "fake-server")]
+pub mod fakeserver;
 pub mod swissfiles;
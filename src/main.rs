@@ -1,26 +1,45 @@
-/// Hey there! 
-/// As you can see, Im a real noob in Rust and dev in general, so please be kind with me.
-/// I hope someone with no skill issues could refactor the wole code base and make it readable and maintainable.
-/// Sorry for the mess x) at least it seems to work for now \o/
-
-mod api;
-mod errors;
-mod swissfiles;
-use std::path::PathBuf;
+// Hey there! 
+// As you can see, Im a real noob in Rust and dev in general, so please be kind with me.
+// I hope someone with no skill issues could refactor the wole code base and make it readable and maintainable.
+// Sorry for the mess x) at least it seems to work for now \o/
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, OnceLock};
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
-use swissfiles::uploadparameters::UploadParameters;
-use swissfiles::Swissfiles;
+use swish::api::chunks::{validate_chunk_size, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+use swish::api::endpoint::{Endpoint, DEFAULT_BASE_URL};
+use swish::api::retry::RetryPolicy;
+use swish::cancel::CancelToken;
+use swish::swissfiles::conflict::OnConflict;
+use swish::swissfiles::progress::NoProgress;
+use swish::swissfiles::selection::FileSelection;
+use swish::swissfiles::uploadparameters::UploadParameters;
+use swish::swissfiles::Swissfiles;
 
 use clap::Parser;
-use errors::SwishError;
+use dialoguer::MultiSelect;
+use indicatif::HumanBytes;
+use swish::errors::SwishError;
 use regex::Regex;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+const MAX_JOBS: usize = 16;
+const MAX_ATTEMPTS: u32 = 100;
 
 #[derive(clap::Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// could be a file or a folder or a link
-    file: String,
+    #[arg(required = true)]
+    file: Option<String>,
 
     /// Sets the password for the file(s) downloaded / uploaded
     #[arg(short, long, value_name = "password")]
@@ -38,46 +57,244 @@ struct Cli {
     #[arg(short, long, value_name = "30", value_parser = validate_duration)]
     duration: Option<String>,
 
+    /// Upload the targets of symlinks found in folders instead of skipping them
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Upload hidden files and folders (starting with a dot) found in folders
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Only upload the files of a folder matching this glob (can be repeated)
+    #[arg(long, value_name = "glob")]
+    include: Vec<String>,
+
+    /// Leave out the files and folders of a folder, or the files of a transfer, matching this glob (can be repeated)
+    #[arg(long, value_name = "glob")]
+    exclude: Vec<String>,
+
+    /// Leave out what the .gitignore and .swishignore files of a folder list
+    #[arg(long)]
+    ignore_files: bool,
+
+    /// Define where the progress of an upload is saved, to resume it if it gets interrupted
+    #[arg(long, value_name = "dir", default_value = ".")]
+    state_dir: PathBuf,
+
+    /// Do not save the progress of the upload
+    #[arg(long)]
+    no_state: bool,
+
+    /// Define the size of the chunks files are sent in (K, M and G units are powers of 1024)
+    #[arg(long, value_name = "8M", default_value = "50M", value_parser = parse_chunk_size)]
+    chunk_size: usize,
+
+    /// Define how many chunks are uploaded, or files downloaded, at once
+    #[arg(short, long, value_name = "1", default_value = "1", value_parser = validate_jobs, global = true)]
+    jobs: usize,
+
+    /// Define how many times a chunk is sent before giving up
+    #[arg(long, value_name = "5", default_value = "5", value_parser = validate_max_attempts, global = true)]
+    max_attempts: u32,
+
+    /// Define how many connections a large file is downloaded over
+    #[arg(long, value_name = "1", default_value = "1", value_parser = validate_segments)]
+    segments: usize,
+
     /// Define an output directory for the downloaded files
     #[arg(short, long, value_name = "output")]
     output: Option<String>,
 
+    /// Only download the files of the transfer matching this glob (can be repeated)
+    #[arg(long, value_name = "glob")]
+    only: Vec<String>,
+
+    /// Only download the file of the transfer with this UUID (can be repeated)
+    #[arg(long, value_name = "uuid")]
+    file_uuid: Vec<String>,
+
+    /// Pick the files of the transfer to download from a list
+    #[arg(long)]
+    select: bool,
+
+    /// Define what happens to files already in the output directory: overwrite, skip, skip-same-size, rename or fail
+    #[arg(long, value_name = "policy", default_value = "overwrite")]
+    on_conflict: OnConflict,
+
+    /// Complete partially downloaded files instead of downloading them again
+    #[arg(short = 'c', long = "continue")]
+    continue_downloads: bool,
+
     /// Enable verbose mode
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// Print the result as a JSON object, and errors as one on stderr, instead of progress bars and logs
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Define the SwissTransfer instance to talk to (the API is expected under /api)
+    #[arg(long, value_name = "url", env = "SWISH_API_URL", default_value = DEFAULT_BASE_URL, global = true)]
+    api_url: String,
 }
 
-fn main() -> Result<(), SwishError> {
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Resume an interrupted upload from the state file it left behind
+    Resume {
+        /// State file of the upload (swish-<container>.state)
+        state: PathBuf,
+    },
+    /// Show what a transfer holds without downloading it
+    Info {
+        /// Link of the transfer
+        link: String,
+
+        /// Sets the password of the transfer
+        #[arg(short, long, value_name = "password")]
+        password: Option<String>,
+    },
+}
+
+fn main() -> ExitCode {
 
     let cli = Cli::parse();
 
-    // Initialize logger
+    // Initialize logger, logs go to stderr so they never mix with the output
     let mut logger = SimpleLogger::new();
     if cli.verbose {
         logger = logger.with_level(LevelFilter::Debug);
+    } else if cli.json {
+        logger = logger.with_level(LevelFilter::Off);
     } else {
         logger = logger.with_level(LevelFilter::Info);
     }
     logger.init().unwrap();
 
+    let json = cli.json;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            if json {
+                eprintln!("{}", error.to_json());
+            } else {
+                eprintln!("Error: {}", error);
+            }
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), SwishError> {
+
+    let retry = RetryPolicy {
+        max_attempts: cli.max_attempts,
+        ..RetryPolicy::default()
+    };
+
+    let endpoint = Endpoint::new(&cli.api_url);
+
+    match cli.command {
+        Some(Command::Resume { state }) => {
+            let mut local_files = Swissfiles::from_upload_state(&state)?;
+            local_files.jobs = cli.jobs;
+            local_files.retry = retry;
+            if cli.json {
+                local_files.progress = Arc::new(NoProgress);
+            }
+            let download_link = local_files.upload()?;
+            if cli.json {
+                println!("{}", serde_json::to_value(local_files.upload_report(&download_link))?);
+            } else {
+                println!("Download link: {}", download_link);
+            }
+
+            return Ok(());
+        }
+        Some(Command::Info { link, password }) => {
+            if !is_swisstransfer_link(&link, &endpoint) {
+                return Err(SwishError::InvalidUrl { url: link });
+            }
+            let swissfiles = Swissfiles::new_remotefiles(&link, password.as_deref(), &endpoint)?;
+            let info = swissfiles.info();
+            if cli.json {
+                println!("{}", serde_json::to_value(&info)?);
+            } else {
+                println!("{}", info);
+            }
+
+            return Ok(());
+        }
+        None => (),
+    }
 
-    let arg = cli.file;
+    let arg = cli.file.unwrap_or_default();
 
 
     //check if the arg is a link
-    if is_swisstransfer_link(&arg) {
+    if is_swisstransfer_link(&arg, &endpoint) {
         //Construct the swissfiles from the link
-        let swissfiles = Swissfiles::new_remotefiles(&arg, cli.password.as_deref())?;
+        let mut swissfiles = Swissfiles::new_remotefiles(&arg, cli.password.as_deref(), &endpoint)?;
+        swissfiles.continue_downloads = cli.continue_downloads;
+        swissfiles.jobs = cli.jobs;
+        swissfiles.segments = cli.segments;
+        swissfiles.on_conflict = cli.on_conflict;
+        if cli.json {
+            swissfiles.progress = Arc::new(NoProgress);
+        }
+
+        let selection = FileSelection {
+            only: cli.only,
+            exclude: cli.exclude,
+            file_uuids: cli.file_uuid,
+        };
+        if !selection.is_empty() {
+            swissfiles.select(&selection)?;
+        }
+        if cli.select {
+            let file_uuids = pick_files(&swissfiles)?;
+            swissfiles.select(&FileSelection {
+                file_uuids,
+                ..FileSelection::default()
+            })?;
+        }
+
+        // Ctrl-C stops the downloads so their part files get removed
+        swissfiles.cancel = cancel_on_interrupt();
 
         //Download the files
-        swissfiles.download(cli.output.map(PathBuf::from).as_ref())?;
+        let downloaded = swissfiles.download(cli.output.map(PathBuf::from).as_ref())?;
+
+        if cli.json {
+            let mut files = Vec::new();
+            for file in downloaded {
+                files.push(json!({
+                    "name": file.name,
+                    "path": file.path,
+                    "size": file.size,
+                    "sha256": sha256_file(&file.path)?,
+                }));
+            }
+            println!("{}", json!({ "container_uuid": swissfiles.container_uuid, "files": files }));
+        }
 
         return Ok(());
     }
     //check if the arg is a path
     if path_exists(&arg) {
         let path = PathBuf::from(&arg);
-        let mut params = UploadParameters::default();
+        let mut params = UploadParameters {
+            follow_symlinks: cli.follow_symlinks,
+            include_hidden: cli.include_hidden,
+            include: cli.include,
+            exclude: cli.exclude,
+            use_ignore_files: cli.ignore_files,
+            state_dir: (!cli.no_state).then_some(cli.state_dir),
+            chunk_size: cli.chunk_size,
+            jobs: cli.jobs,
+            retry,
+            ..UploadParameters::default()
+        };
 
         if let Some(password) = cli.password {
             params.password = password;
@@ -95,9 +312,23 @@ fn main() -> Result<(), SwishError> {
             params.duration = duration.parse().unwrap();
         }
 
-        let local_files = Swissfiles::new_localfiles(path, &params)?;
+        let mut local_files = Swissfiles::new_localfiles(path, &params, &endpoint)?;
+        if cli.json {
+            local_files.progress = Arc::new(NoProgress);
+        }
         let download_link = local_files.upload()?;
-        println!("Download link: {}", download_link);
+        if cli.json {
+            let mut report = serde_json::to_value(local_files.upload_report(&download_link))?;
+            report["parameters"] = json!({
+                "duration": params.duration,
+                "number_of_download": params.number_of_download,
+                "message": params.message,
+                "password_protected": !params.password.is_empty(),
+            });
+            println!("{}", report);
+        } else {
+            println!("Download link: {}", download_link);
+        }
 
         return Ok(());
     }
@@ -105,8 +336,68 @@ fn main() -> Result<(), SwishError> {
     Err(SwishError::InvalidUrl { url: arg })
 }
 
-fn is_swisstransfer_link(link: &str) -> bool {
-    let re = Regex::new(r"^https://www\.swisstransfer\.com/d/[a-f0-9]{8}-[a-f0-9]{4}-[a-f0-9]{4}-[a-f0-9]{4}-[a-f0-9]{12}$").unwrap();
+/// Let the user tick the files of the transfer to download, returns their UUIDs
+fn pick_files(swissfiles: &Swissfiles) -> Result<Vec<String>, SwishError> {
+    let files: Vec<_> = swissfiles.remote_files().collect();
+    let items: Vec<String> = files
+        .iter()
+        .map(|file| format!("{} ({})", file.name, HumanBytes(file.size)))
+        .collect();
+
+    let picked = MultiSelect::new()
+        .with_prompt("Files to download (space to select, enter to confirm)")
+        .items(&items)
+        .interact_opt()
+        .map_err(|dialoguer::Error::IO(error)| SwishError::FileError { error })?
+        .unwrap_or_default();
+
+    if picked.is_empty() {
+        return Err(SwishError::NothingToDownload);
+    }
+    Ok(picked.into_iter().map(|index| files[index].uuid.clone()).collect())
+}
+
+/// Cancelled by the first Ctrl-C
+static INTERRUPTED: OnceLock<CancelToken> = OnceLock::new();
+
+/// Token cancelled by Ctrl-C, pressing it a second time exits right away.
+/// Only unix signals are caught, elsewhere Ctrl-C keeps its default behavior
+fn cancel_on_interrupt() -> CancelToken {
+    let cancel = INTERRUPTED.get_or_init(CancelToken::new).clone();
+    #[cfg(unix)]
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+    cancel
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    // Only async-signal-safe calls are allowed here, the token only sets a flag
+    if let Some(cancel) = INTERRUPTED.get() {
+        if cancel.is_cancelled() {
+            unsafe { libc::_exit(130) };
+        }
+        cancel.cancel();
+    }
+}
+
+/// SHA-256 of the content of a file, in hexadecimal
+fn sha256_file(path: &Path) -> Result<String, SwishError> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn is_swisstransfer_link(link: &str, endpoint: &Endpoint) -> bool {
+    let re = Regex::new(&format!(
+        r"^{}/d/[a-f0-9]{{8}}-[a-f0-9]{{4}}-[a-f0-9]{{4}}-[a-f0-9]{{4}}-[a-f0-9]{{12}}$",
+        regex::escape(&endpoint.base_url)
+    ))
+    .unwrap();
     re.is_match(link)
 }
 
@@ -117,7 +408,7 @@ fn path_exists(path: &str) -> bool {
 
 fn validate_number_download(val: &str) -> Result<String, String> {
     let number = val.parse::<u16>().map_err(|_| "Must be a valid number")?;
-    if number < 1 || number > 250 {
+    if !(1..=250).contains(&number) {
         Err(String::from(
             "Number of downloads must be between 1 and 250",
         ))
@@ -126,6 +417,61 @@ fn validate_number_download(val: &str) -> Result<String, String> {
     }
 }
 
+fn validate_jobs(val: &str) -> Result<usize, String> {
+    let number = val.parse::<usize>().map_err(|_| "Must be a valid number")?;
+    if (1..=MAX_JOBS).contains(&number) {
+        Ok(number)
+    } else {
+        Err(format!("Jobs must be between 1 and {}", MAX_JOBS))
+    }
+}
+
+/// Parse a size such as `8M`, `512KiB` or `1048576` (bytes)
+fn parse_size(val: &str) -> Option<usize> {
+    let val = val.trim();
+    let split = val.find(|c: char| !c.is_ascii_digit()).unwrap_or(val.len());
+    let (number, unit) = val.split_at(split);
+    let number = number.parse::<usize>().ok()?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+fn parse_chunk_size(val: &str) -> Result<usize, String> {
+    let size = parse_size(val).ok_or("Must be a size such as 8M, 512K or 1048576")?;
+    match validate_chunk_size(size) {
+        Ok(()) => Ok(size),
+        Err(_) => Err(format!(
+            "Chunk size must be between {}M and {}M",
+            MIN_CHUNK_SIZE / 1024 / 1024,
+            MAX_CHUNK_SIZE / 1024 / 1024
+        )),
+    }
+}
+
+fn validate_max_attempts(val: &str) -> Result<u32, String> {
+    let number = val.parse::<u32>().map_err(|_| "Must be a valid number")?;
+    if (1..=MAX_ATTEMPTS).contains(&number) {
+        Ok(number)
+    } else {
+        Err(format!("Attempts must be between 1 and {}", MAX_ATTEMPTS))
+    }
+}
+
+fn validate_segments(val: &str) -> Result<usize, String> {
+    let number = val.parse::<usize>().map_err(|_| "Must be a valid number")?;
+    if (1..=MAX_JOBS).contains(&number) {
+        Ok(number)
+    } else {
+        Err(format!("Segments must be between 1 and {}", MAX_JOBS))
+    }
+}
+
 fn validate_duration(val: &str) -> Result<String, String> {
     let number = val.parse::<u32>().map_err(|_| "Must be a valid number")?;
     if [1, 7, 15, 30].contains(&number) {
@@ -141,24 +487,34 @@ mod tests {
 
     #[test]
     fn test_is_swisstransfer_link() {
+        let endpoint = Endpoint::default();
         let link = "https://www.swisstransfer.com/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b";
-        assert_eq!(is_swisstransfer_link(link), true);
+        assert!(is_swisstransfer_link(link, &endpoint));
         let link = "http://www.swisstransfer.com/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b/";
-        assert_eq!(is_swisstransfer_link(link), false);
+        assert!(!is_swisstransfer_link(link, &endpoint));
         let link = "https://www.swisstransfer.ch/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b/";
-        assert_eq!(is_swisstransfer_link(link), false);
+        assert!(!is_swisstransfer_link(link, &endpoint));
         let link = "www.swisstransfer.com/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b";
-        assert_eq!(is_swisstransfer_link(link), false);
+        assert!(!is_swisstransfer_link(link, &endpoint));
         let link = "https://www.swisstransfer.com/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b";
-        assert_eq!(is_swisstransfer_link(link), false);
+        assert!(!is_swisstransfer_link(link, &endpoint));
+    }
+
+    #[test]
+    fn test_is_swisstransfer_link_custom_endpoint() {
+        let endpoint = Endpoint::new("http://127.0.0.1:8080");
+        let link = "http://127.0.0.1:8080/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b";
+        assert!(is_swisstransfer_link(link, &endpoint));
+        let link = "https://www.swisstransfer.com/d/8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b";
+        assert!(!is_swisstransfer_link(link, &endpoint));
     }
 
     #[test]
     fn test_path_exists() {
         let path = "Cargo.toml";
-        assert_eq!(path_exists(path), true);
+        assert!(path_exists(path));
         let path = "Cargo.toml2";
-        assert_eq!(path_exists(path), false);
+        assert!(!path_exists(path));
     }
 
     #[test]
@@ -179,6 +535,62 @@ mod tests {
         assert_eq!(validate_number_download(number), Err(String::from("Must be a valid number")));
     }
 
+    #[test]
+    fn test_validate_jobs() {
+        assert_eq!(validate_jobs("1"), Ok(1));
+        assert_eq!(validate_jobs("16"), Ok(16));
+        assert_eq!(validate_jobs("0"), Err(String::from("Jobs must be between 1 and 16")));
+        assert_eq!(validate_jobs("17"), Err(String::from("Jobs must be between 1 and 16")));
+        assert_eq!(validate_jobs("a"), Err(String::from("Must be a valid number")));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Some(1048576));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("8M"), Some(8 * 1024 * 1024));
+        assert_eq!(parse_size("8mib"), Some(8 * 1024 * 1024));
+        assert_eq!(parse_size("1 GB"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("8T"), None);
+        assert_eq!(parse_size("-8M"), None);
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(parse_chunk_size("8M"), Ok(8 * 1024 * 1024));
+        assert_eq!(parse_chunk_size("100M"), Ok(100 * 1024 * 1024));
+        assert_eq!(
+            parse_chunk_size("512K"),
+            Err(String::from("Chunk size must be between 1M and 100M"))
+        );
+        assert_eq!(
+            parse_chunk_size("1G"),
+            Err(String::from("Chunk size must be between 1M and 100M"))
+        );
+        assert_eq!(
+            parse_chunk_size("eight"),
+            Err(String::from("Must be a size such as 8M, 512K or 1048576"))
+        );
+    }
+
+    #[test]
+    fn test_validate_max_attempts() {
+        assert_eq!(validate_max_attempts("1"), Ok(1));
+        assert_eq!(validate_max_attempts("100"), Ok(100));
+        assert_eq!(validate_max_attempts("0"), Err(String::from("Attempts must be between 1 and 100")));
+        assert_eq!(validate_max_attempts("101"), Err(String::from("Attempts must be between 1 and 100")));
+        assert_eq!(validate_max_attempts("a"), Err(String::from("Must be a valid number")));
+    }
+
+    #[test]
+    fn test_validate_segments() {
+        assert_eq!(validate_segments("4"), Ok(4));
+        assert_eq!(validate_segments("0"), Err(String::from("Segments must be between 1 and 16")));
+        assert_eq!(validate_segments("17"), Err(String::from("Segments must be between 1 and 16")));
+        assert_eq!(validate_segments("a"), Err(String::from("Must be a valid number")));
+    }
+
     #[test]
     fn test_validate_duration() {
         let duration = "30";
@@ -0,0 +1,85 @@
+//! Async versions of the `Swissfiles` operations, only built with the `async` feature.
+//!
+//! They are what the blocking operations run: requests are futures driven by the runtime, the
+//! transfers of a `CurlTransport` share one thread of its own that waits on the network, so no
+//! thread of the runtime is held while a transfer goes on.
+//! Dropping one of these futures cancels the transfer: the requests in flight are aborted, waits
+//! between attempts stop and no other request is sent. What was already uploaded or downloaded
+//! is left behind, a state file or `continue_downloads` picks it up from there.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::api::curltransport::CurlTransport;
+use crate::api::endpoint::Endpoint;
+use crate::api::transport::Transport;
+use crate::errors::SwishError;
+
+use super::report::DownloadedFile;
+use super::uploadparameters::UploadParameters;
+use super::Swissfiles;
+
+impl Swissfiles {
+    /// Same as `new_remotefiles`, without blocking the runtime
+    pub async fn new_remotefiles_async(
+        swisstransfer_link: &str,
+        password: Option<&str>,
+        endpoint: &Endpoint,
+    ) -> Result<Self, SwishError> {
+        Self::new_remotefiles_async_with_transport(
+            swisstransfer_link,
+            password,
+            endpoint,
+            Arc::new(CurlTransport::new()),
+        )
+        .await
+    }
+
+    /// Same as `new_remotefiles_with_transport`, without blocking the runtime
+    pub async fn new_remotefiles_async_with_transport(
+        swisstransfer_link: &str,
+        password: Option<&str>,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, SwishError> {
+        Self::open_remotefiles(swisstransfer_link, password, endpoint, transport).await
+    }
+
+    /// Same as `new_localfiles`, without blocking the runtime
+    pub async fn new_localfiles_async(
+        path: PathBuf,
+        upload_parameter: &UploadParameters,
+        endpoint: &Endpoint,
+    ) -> Result<Self, SwishError> {
+        Self::new_localfiles_async_with_transport(
+            path,
+            upload_parameter,
+            endpoint,
+            Arc::new(CurlTransport::new()),
+        )
+        .await
+    }
+
+    /// Same as `new_localfiles_with_transport`, without blocking the runtime
+    pub async fn new_localfiles_async_with_transport(
+        path: PathBuf,
+        upload_parameter: &UploadParameters,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, SwishError> {
+        Self::create_localfiles(path, upload_parameter, endpoint, transport).await
+    }
+
+    /// Same as `upload`, without blocking the runtime. `cancel` still stops it
+    pub async fn upload_async(&self) -> Result<String, SwishError> {
+        self.run_upload().await
+    }
+
+    /// Same as `download`, without blocking the runtime. `cancel` still stops it
+    pub async fn download_async(
+        &self,
+        custom_out_path: Option<&PathBuf>,
+    ) -> Result<Vec<DownloadedFile>, SwishError> {
+        self.run_download(custom_out_path).await
+    }
+}
@@ -0,0 +1,28 @@
+//! Running the async core of `Swissfiles` from its blocking API.
+
+use std::future::Future;
+
+/// Run `future` to completion on a runtime of its own.
+/// Called from within a runtime, an async test for instance, it is run on a thread of its own as
+/// runtimes can not be nested
+pub(crate) fn block_on<F>(future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    if tokio::runtime::Handle::try_current().is_err() {
+        return run(future);
+    }
+    std::thread::scope(|scope| match scope.spawn(|| run(future)).join() {
+        Ok(output) => output,
+        Err(panic) => std::panic::resume_unwind(panic),
+    })
+}
+
+fn run<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("the tokio runtime can not be started")
+        .block_on(future)
+}
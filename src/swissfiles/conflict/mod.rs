@@ -0,0 +1,78 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::errors::SwishError;
+
+/// What to do when a downloaded file would replace one already on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Keep the existing file and do not download
+    Skip,
+    /// Keep the existing file if it has the expected size, replace it otherwise
+    SkipSameSize,
+    /// Download next to it as `name (1).ext`, `name (2).ext`...
+    Rename,
+    /// Do not download and report an error
+    Fail,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "overwrite" => Ok(OnConflict::Overwrite),
+            "skip" => Ok(OnConflict::Skip),
+            "skip-same-size" => Ok(OnConflict::SkipSameSize),
+            "rename" => Ok(OnConflict::Rename),
+            "fail" => Ok(OnConflict::Fail),
+            _ => Err(String::from(
+                "Must be overwrite, skip, skip-same-size, rename or fail",
+            )),
+        }
+    }
+}
+
+impl OnConflict {
+    /// Where a file of `size` bytes meant for `path` should be written, None if it should not be downloaded
+    pub fn resolve(self, path: &Path, size: u64) -> Result<Option<PathBuf>, SwishError> {
+        let existing = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(Some(path.to_path_buf())),
+        };
+
+        match self {
+            OnConflict::Overwrite => Ok(Some(path.to_path_buf())),
+            OnConflict::Skip => Ok(None),
+            OnConflict::SkipSameSize if existing.len() == size => Ok(None),
+            OnConflict::SkipSameSize => Ok(Some(path.to_path_buf())),
+            OnConflict::Rename => Ok(Some(free_path(path))),
+            OnConflict::Fail => Err(SwishError::FileExists {
+                path: path.display().to_string(),
+            }),
+        }
+    }
+}
+
+/// First `name (n).ext` next to `path` that does not exist yet
+fn free_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    let extension = path.extension();
+
+    (1..)
+        .map(|n| {
+            let mut name = OsString::from(stem);
+            name.push(format!(" ({})", n));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use crate::errors::SwishError;
+
+use super::uploadparameters::UploadParameters;
+
+/// Ignore files honored when `UploadParameters::use_ignore_files` is set, they use the gitignore syntax
+pub const IGNORE_FILES: &[&str; 2] = &[".gitignore", ".swishignore"];
+
+/// A file to upload: where it lives on disk and the name it is declared with.
+/// For folder uploads the name is the path relative to the folder, using `/` as separator
+/// (e.g. `sub/dir/file.txt`) so the tree can be rebuilt on download.
+#[derive(Debug, Clone)]
+pub struct LocalEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+}
+
+/// What decides whether a file found in a folder gets uploaded
+struct Filters<'a> {
+    upload_parameter: &'a UploadParameters,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    /// Ignore files of the folders being walked, the deepest one last
+    ignores: Vec<Gitignore>,
+}
+
+/// A glob matches a file or folder if it matches its relative name or only its own name,
+/// so `target` skips every `target` folder and `docs/*.md` only the markdown files of `docs`
+pub(crate) fn glob_matches(globs: &GlobSet, name: &str) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    globs.is_match(name) || globs.is_match(file_name)
+}
+
+impl Filters<'_> {
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for ignore in self.ignores.iter().rev() {
+            match ignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => (),
+            }
+        }
+        false
+    }
+
+    fn skips_dir(&self, path: &Path, name: &str) -> bool {
+        glob_matches(&self.exclude, name) || self.is_ignored(path, true)
+    }
+
+    fn skips_file(&self, path: &Path, name: &str) -> bool {
+        if glob_matches(&self.exclude, name) || self.is_ignored(path, false) {
+            return true;
+        }
+        match &self.include {
+            Some(include) => !glob_matches(include, name),
+            None => false,
+        }
+    }
+}
+
+/// Walk `path` and list every file to upload.
+/// A single file is returned as is, even when hidden or filtered out.
+pub fn collect_files(
+    path: &Path,
+    upload_parameter: &UploadParameters,
+) -> Result<Vec<LocalEntry>, SwishError> {
+    if !path.is_dir() {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        return Ok(vec![LocalEntry {
+            path: path.to_path_buf(),
+            name,
+            size: path.metadata()?.len(),
+        }]);
+    }
+
+    let mut filters = Filters {
+        upload_parameter,
+        include: if upload_parameter.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(&upload_parameter.include)?)
+        },
+        exclude: build_globset(&upload_parameter.exclude)?,
+        ignores: Vec::new(),
+    };
+
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert(path.canonicalize()?);
+    walk(path, "", &mut filters, &mut visited, &mut entries)?;
+
+    if entries.is_empty() {
+        return Err(SwishError::NothingToUpload {
+            path: path.display().to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+pub(crate) fn build_globset(patterns: &[String]) -> Result<GlobSet, SwishError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|error| SwishError::InvalidPattern {
+            pattern: pattern.clone(),
+            error: error.to_string(),
+        })?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|error| SwishError::InvalidPattern {
+        pattern: patterns.join(", "),
+        error: error.to_string(),
+    })
+}
+
+fn build_ignore(dir: &Path) -> Result<Option<Gitignore>, SwishError> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for ignore_file in IGNORE_FILES {
+        let path = dir.join(ignore_file);
+        if path.is_file() {
+            if let Some(error) = builder.add(&path) {
+                return Err(SwishError::InvalidPattern {
+                    pattern: path.display().to_string(),
+                    error: error.to_string(),
+                });
+            }
+            found = true;
+        }
+    }
+    if !found {
+        return Ok(None);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|error| SwishError::InvalidPattern {
+            pattern: dir.display().to_string(),
+            error: error.to_string(),
+        })
+}
+
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    filters: &mut Filters,
+    visited: &mut HashSet<PathBuf>,
+    entries: &mut Vec<LocalEntry>,
+) -> Result<(), SwishError> {
+    let ignore = if filters.upload_parameter.use_ignore_files {
+        build_ignore(dir)?
+    } else {
+        None
+    };
+    let has_ignore = ignore.is_some();
+    filters.ignores.extend(ignore);
+
+    // Sort so the same folder is always declared in the same order
+    let mut children = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let file_name = child.file_name().to_string_lossy().to_string();
+        let name = format!("{}{}", prefix, file_name);
+        let path = child.path();
+
+        if file_name.starts_with('.') && !filters.upload_parameter.include_hidden {
+            log::debug!("Skipping hidden file {}", path.display());
+            continue;
+        }
+
+        let mut metadata = child.metadata()?;
+        if metadata.file_type().is_symlink() {
+            if !filters.upload_parameter.follow_symlinks {
+                log::debug!("Skipping symlink {}", path.display());
+                continue;
+            }
+            metadata = match path.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    log::warn!("Skipping broken symlink {}", path.display());
+                    continue;
+                }
+            };
+        }
+
+        if metadata.is_dir() {
+            if filters.skips_dir(&path, &name) {
+                log::debug!("Skipping filtered folder {}", path.display());
+                continue;
+            }
+            // A symlink pointing to one of its parents would make us loop forever
+            let canonical = path.canonicalize()?;
+            if !visited.insert(canonical.clone()) {
+                log::warn!("Skipping {} as it was already visited", path.display());
+                continue;
+            }
+            walk(&path, &format!("{}/", name), filters, visited, entries)?;
+            visited.remove(&canonical);
+        } else if metadata.is_file() {
+            if filters.skips_file(&path, &name) {
+                log::debug!("Skipping filtered file {}", path.display());
+                continue;
+            }
+            entries.push(LocalEntry {
+                path,
+                name,
+                size: metadata.len(),
+            });
+        } else {
+            log::debug!("Skipping {} as it is not a regular file", path.display());
+        }
+    }
+
+    if has_ignore {
+        filters.ignores.pop();
+    }
+    Ok(())
+}
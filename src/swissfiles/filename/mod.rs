@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use crate::errors::SwishError;
+
+/// Names Windows keeps for devices, with or without an extension
+#[cfg(windows)]
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Relative path a file named `name` by the server can be written to.
+/// Names of files uploaded from a folder contain `/`, each part becomes a directory.
+/// The name comes from whoever created the transfer, anything that could end up outside the
+/// output directory is refused. Names this system can not create are changed instead
+pub fn safe_path(name: &str) -> Result<PathBuf, SwishError> {
+    let unsafe_name = |reason: &'static str| SwishError::UnsafeFileName {
+        name: name.to_string(),
+        reason,
+    };
+
+    if name.is_empty() {
+        return Err(unsafe_name("it is empty"));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(unsafe_name("it contains control characters"));
+    }
+    if name.starts_with(['/', '\\']) {
+        return Err(unsafe_name("it is an absolute path"));
+    }
+    // `C:file` is relative to the current directory of drive C on Windows
+    let mut chars = name.chars();
+    if chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.next() == Some(':') {
+        return Err(unsafe_name("it starts with a drive letter"));
+    }
+
+    // Windows also splits paths on backslashes
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => return Err(unsafe_name("it contains an empty directory name")),
+            ".." => return Err(unsafe_name("it points to a parent directory")),
+            _ => path.push(portable(part)),
+        }
+    }
+    Ok(path)
+}
+
+/// `part` changed so that Windows creates it as named: a colon would pick a drive or an
+/// alternate data stream, trailing dots and spaces get dropped and device names open the device
+#[cfg(windows)]
+fn portable(part: &str) -> String {
+    let mut part = part.replace(':', "_");
+    if part.ends_with(['.', ' ']) {
+        part.push('_');
+    }
+    let stem = part.split('.').next().unwrap_or(&part).trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        part.insert(0, '_');
+    }
+    part
+}
+
+#[cfg(not(windows))]
+fn portable(part: &str) -> &str {
+    part
+}
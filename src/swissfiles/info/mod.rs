@@ -0,0 +1,114 @@
+use std::fmt;
+
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use crate::swissfiles::swissfile::RemoteSwissfile;
+
+/// What a transfer holds, as shown by `swish info`
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferInfo {
+    pub container_uuid: String,
+    /// Size of all the files together, in bytes
+    pub total_size: u64,
+    pub files: Vec<FileInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileInfo {
+    pub uuid: String,
+    pub name: String,
+    /// Size in bytes
+    pub size: u64,
+    pub mime_type: String,
+    pub created_date: String,
+    pub expired_date: String,
+    /// Number of times the file has been downloaded
+    pub download_counter: u64,
+    /// State of the virus scan, as reported by the server
+    pub virus_scan: String,
+}
+
+impl TransferInfo {
+    pub fn new<'a>(
+        container_uuid: &str,
+        files: impl IntoIterator<Item = &'a RemoteSwissfile>,
+    ) -> Self {
+        let files: Vec<FileInfo> = files.into_iter().map(FileInfo::from).collect();
+        Self {
+            container_uuid: container_uuid.to_string(),
+            total_size: files.iter().map(|file| file.size).sum(),
+            files,
+        }
+    }
+}
+
+impl From<&RemoteSwissfile> for FileInfo {
+    fn from(file: &RemoteSwissfile) -> Self {
+        Self {
+            uuid: file.uuid.clone(),
+            name: file.name.clone(),
+            size: file.size,
+            mime_type: file.mime_type.clone(),
+            created_date: file.created_date.clone(),
+            expired_date: file.expired_date.clone(),
+            download_counter: file.download_counter,
+            virus_scan: file.e_virus_scan.clone(),
+        }
+    }
+}
+
+impl fmt::Display for TransferInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Transfer {}: {} file(s), {}",
+            self.container_uuid,
+            self.files.len(),
+            HumanBytes(self.total_size)
+        )?;
+        // Files of a transfer expire together
+        if let Some(expired_date) = self.files.iter().map(|file| &file.expired_date).max() {
+            write!(f, ", expires {}", expired_date)?;
+        }
+        writeln!(f)?;
+
+        let header = ["NAME", "SIZE", "TYPE", "DOWNLOADS", "VIRUS SCAN", "UUID"];
+        let rows: Vec<[String; 6]> = self
+            .files
+            .iter()
+            .map(|file| {
+                [
+                    file.name.clone(),
+                    HumanBytes(file.size).to_string(),
+                    file.mime_type.clone(),
+                    file.download_counter.to_string(),
+                    file.virus_scan.clone(),
+                    file.uuid.clone(),
+                ]
+            })
+            .collect();
+
+        let mut widths = header.map(|title| title.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let header = header.map(String::from);
+        for row in std::iter::once(&header).chain(&rows) {
+            writeln!(f)?;
+            for (column, (cell, width)) in row.iter().zip(widths).enumerate() {
+                // Sizes and counters are right aligned, the last column is not padded
+                match column {
+                    0 => write!(f, "{:<width$}", cell)?,
+                    1 | 3 => write!(f, "  {:>width$}", cell)?,
+                    5 => write!(f, "  {}", cell)?,
+                    _ => write!(f, "  {:<width$}", cell)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
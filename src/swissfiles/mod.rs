@@ -1,38 +1,123 @@
 use std::{
     fmt,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+#[cfg(feature = "async")]
+mod asynchronous;
+mod blocking;
+pub mod conflict;
+pub mod filecollector;
+pub mod filename;
+pub mod info;
+pub mod progress;
+pub mod report;
+mod scheduler;
+pub mod selection;
 mod swissfile;
 pub mod uploadparameters;
+pub mod uploadstate;
 use crate::{
-    api::{get, post},
+    api::{
+        chunks::validate_chunk_size,
+        curltransport::CurlTransport,
+        endpoint::Endpoint,
+        model::{
+            self, ContainerFile, ContainerRequest, ContainerResponse, LinksResponse, LinksStatus,
+            UploadCompleteRequest, UploadCompleteResponse,
+        },
+        retry::RetryPolicy,
+        transport::Transport,
+    },
+    cancel::CancelToken,
     errors::SwishError,
-    swissfiles::swissfile::{RemoteSwissfile, Swissfile},
+    swissfiles::swissfile::{LocalSwissfile, Swissfile},
 };
 use base64::prelude::*;
-use serde_json::json;
 
+use self::blocking::block_on;
+use self::conflict::OnConflict;
+use self::filecollector::{collect_files, LocalEntry};
+use self::info::TransferInfo;
+use self::progress::{IndicatifProgress, ProgressObserver};
+use self::report::{DownloadedFile, UploadReport, UploadedFile};
+use self::scheduler::{download_files, upload_chunks};
+use self::selection::FileSelection;
 use self::uploadparameters::UploadParameters;
+use self::uploadstate::{FileState, StateFile, UploadState};
 
-const SWISSTRANSFER_API: &str = "https://www.swisstransfer.com/api";
+pub use self::swissfile::{DownloadOptions, RemoteSwissfile, MIN_SEGMENT_SIZE, PART_EXTENSION};
 
+#[derive(Clone)]
 pub struct Swissfiles {
     pub files: Vec<Swissfile>,
     pub container_uuid: String,
+    pub endpoint: Endpoint,
+    /// What every request of the transfer is sent with
+    pub transport: Arc<dyn Transport>,
+    /// Where the progress of an upload is saved, if anywhere
+    pub state_file: Option<StateFile>,
+    /// Number of chunks uploaded, or files downloaded, at once
+    pub jobs: usize,
+    /// How failed chunk uploads are sent again
+    pub retry: RetryPolicy,
+    /// Complete partially downloaded files instead of downloading them again
+    pub continue_downloads: bool,
+    /// Number of connections a large file is downloaded over
+    pub segments: usize,
+    /// What happens to files already in the output directory
+    pub on_conflict: OnConflict,
+    /// When the transfer expires, if the server said so
+    pub expired_date: Option<String>,
+    /// Told how transfers go, progress bars are drawn on stderr by default
+    pub progress: Arc<dyn ProgressObserver>,
+    /// Stops the transfers once cancelled, e.g. on Ctrl-C
+    pub cancel: CancelToken,
 }
 
 impl Swissfiles {
     pub fn new_remotefiles(
         swisstransfer_link: &str,
         password: Option<&str>,
+        endpoint: &Endpoint,
+    ) -> Result<Self, SwishError> {
+        Self::new_remotefiles_with_transport(
+            swisstransfer_link,
+            password,
+            endpoint,
+            Arc::new(CurlTransport::new()),
+        )
+    }
+
+    /// Same as `new_remotefiles`, requests are sent with `transport`
+    pub fn new_remotefiles_with_transport(
+        swisstransfer_link: &str,
+        password: Option<&str>,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, SwishError> {
+        block_on(Self::open_remotefiles(
+            swisstransfer_link,
+            password,
+            endpoint,
+            transport,
+        ))
+    }
+
+    /// What `new_remotefiles_with_transport` runs
+    pub(crate) async fn open_remotefiles(
+        swisstransfer_link: &str,
+        password: Option<&str>,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
     ) -> Result<Self, SwishError> {
         log::debug!("Creating new swissfiles : {}", &swisstransfer_link);
 
         // We might verify link validity there idk
 
-        let download_id = swisstransfer_link.split("/").last().unwrap();
-        let url = format!("{}/links/{}", SWISSTRANSFER_API, download_id);
+        let download_id = swisstransfer_link.rsplit('/').next().unwrap_or_default();
+        let url = endpoint.api(&format!("links/{}", download_id));
 
         // if password is provided, add authorization header
         let auth_header: Option<Vec<String>> = match password {
@@ -52,24 +137,24 @@ impl Swissfiles {
             None => None,
         };
 
-        let response = get(&url, auth_header.clone())?;
-        let response: serde_json::Value = serde_json::from_str(&response)?;
+        let mut response = transport.get(&url, auth_header.clone()).await?;
+        let mut status: LinksStatus = model::parse(response.as_bytes())?;
 
         //  Handling different responses
-        match response["data"]["message"].as_str() {
+        match status.data.message.as_deref() {
             Some("Transfer need a password") => return Err(SwishError::PasswordRequired),
             Some("The password is wrong") => return Err(SwishError::InvalidPassword),
             Some("All file are not finish to virus check") => {
                 loop {
                     // Wait for security checks on Infomaniak's side
-                    print!("Waiting for security checks on Infomaniak's side");
-                    std::thread::sleep(std::time::Duration::from_secs(5));
-                    let response = get(&url, auth_header.clone())?;
-                    let response: serde_json::Value = serde_json::from_str(&response)?;
+                    log::info!("Waiting for security checks on Infomaniak's side");
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    response = transport.get(&url, auth_header.clone()).await?;
+                    status = model::parse(response.as_bytes())?;
 
-                    let message = response["data"]["message"].as_str();
+                    let message = status.data.message.as_deref();
 
-                    if message == Some("All file are finish to virus check") {
+                    if message != Some("All file are not finish to virus check") {
                         break;
                     }
                 }
@@ -79,35 +164,43 @@ impl Swissfiles {
         }
 
         //Retrieve information from the resposne
-        let download_host = response["data"]["downloadHost"]
-            .as_str()
-            .unwrap()
-            .to_string();
-        let link_uuid = response["data"]["linkUUID"].as_str().unwrap().to_string();
-        let container_uuid = response["data"]["container"]["UUID"]
-            .as_str()
-            .unwrap()
-            .to_string();
+        let link = model::parse::<LinksResponse>(response.as_bytes())?.data;
+        let container_uuid = link.container.uuid;
 
         // Build the download base url
-        let download_base_url = format!("https://{}/api/download/{}", &download_host, &link_uuid);
+        let download_base_url =
+            endpoint.host_api(&link.download_host, &format!("download/{}", link.link_uuid));
 
         let mut files = Vec::new();
 
-        for file in response["data"]["container"]["files"].as_array().unwrap() {
+        for file in link.container.files {
             // We should probably generate the download token here and pass it to the Swissfile constructor
-            let swissfile = Swissfile::Remote(RemoteSwissfile::new(
+            let remote_file = RemoteSwissfile::new(
                 file,
                 &download_base_url,
                 &container_uuid,
                 password,
-            ));
-            files.push(swissfile);
+                endpoint,
+                transport.as_ref(),
+            )
+            .await?;
+            files.push(Swissfile::Remote(remote_file));
         }
 
         let swissfiles = Swissfiles {
             files,
             container_uuid,
+            endpoint: endpoint.clone(),
+            transport,
+            state_file: None,
+            jobs: 1,
+            retry: RetryPolicy::default(),
+            continue_downloads: false,
+            segments: 1,
+            on_conflict: OnConflict::default(),
+            expired_date: None,
+            progress: Arc::new(IndicatifProgress::new()),
+            cancel: CancelToken::new(),
         };
 
         Ok(swissfiles)
@@ -116,164 +209,357 @@ impl Swissfiles {
     pub fn new_localfiles(
         path: PathBuf,
         upload_parameter: &UploadParameters,
+        endpoint: &Endpoint,
     ) -> Result<Self, SwishError> {
+        Self::new_localfiles_with_transport(
+            path,
+            upload_parameter,
+            endpoint,
+            Arc::new(CurlTransport::new()),
+        )
+    }
+
+    /// Same as `new_localfiles`, requests are sent with `transport`
+    pub fn new_localfiles_with_transport(
+        path: PathBuf,
+        upload_parameter: &UploadParameters,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, SwishError> {
+        block_on(Self::create_localfiles(
+            path,
+            upload_parameter,
+            endpoint,
+            transport,
+        ))
+    }
 
-        //Wow that sucks
-        let path_clone = path.clone();
+    /// What `new_localfiles_with_transport` runs
+    pub(crate) async fn create_localfiles(
+        path: PathBuf,
+        upload_parameter: &UploadParameters,
+        endpoint: &Endpoint,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, SwishError> {
+        validate_chunk_size(upload_parameter.chunk_size)?;
 
-        let files = if path.is_dir() {
-            std::fs::read_dir(path)
-                .unwrap()
-                .map(|entry| entry.unwrap().path())
-                .collect::<Vec<_>>()
-        } else {
-            vec![path]
-        };
+        let files = collect_files(&path, upload_parameter)?;
 
-        //we need to get the container
-        let container = get_container(&path_clone.clone(), upload_parameter)?;
+        //we need to get the container, files are declared in the same order as `files`
+        let container =
+            get_container(&files, upload_parameter, endpoint, transport.as_ref()).await?;
 
-        let mut swissfiles = Vec::new();
+        let mut local_files = Vec::new();
 
-        for file in files {
-            let swissfile = Swissfile::Local(swissfile::LocalSwissfile::new(file, &container));
-            swissfiles.push(swissfile);
+        for (index, file) in files.into_iter().enumerate() {
+            local_files.push(LocalSwissfile::new(
+                file,
+                &container,
+                index,
+                upload_parameter.chunk_size,
+                endpoint,
+            )?);
         }
 
+        let container_uuid = container.container.uuid.clone();
+
+        let state_file = match &upload_parameter.state_dir {
+            Some(state_dir) => {
+                let mut files = Vec::new();
+                for file in &local_files {
+                    files.push(FileState {
+                        path: file.path.canonicalize()?,
+                        name: file.name.clone(),
+                        size: file.size,
+                        file_uuid: file.files_uuid.clone(),
+                        completed_chunks: Default::default(),
+                        chunk_checksums: Default::default(),
+                    });
+                }
+                let state = UploadState {
+                    base_url: endpoint.base_url.clone(),
+                    container_uuid: container_uuid.clone(),
+                    upload_host: container.upload_host.clone(),
+                    chunk_size: upload_parameter.chunk_size,
+                    files,
+                };
+                let path = state_dir.join(StateFile::file_name(&container_uuid));
+                // Resuming is a nice to have, not a reason to give up on the upload
+                match StateFile::create(path.clone(), state) {
+                    Ok(state_file) => Some(state_file),
+                    Err(error) => {
+                        log::warn!(
+                            "Can not save the upload state to {}, it will not be resumable: {}",
+                            path.display(),
+                            error
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
         Ok(Swissfiles {
-            files: swissfiles,
-            container_uuid: container["container"]["UUID"].as_str().unwrap().to_string(),
+            files: local_files.into_iter().map(Swissfile::Local).collect(),
+            container_uuid,
+            endpoint: endpoint.clone(),
+            transport,
+            state_file,
+            jobs: upload_parameter.jobs,
+            retry: upload_parameter.retry.clone(),
+            continue_downloads: false,
+            segments: 1,
+            on_conflict: OnConflict::default(),
+            expired_date: container.container.expired_date,
+            progress: Arc::new(IndicatifProgress::new()),
+            cancel: CancelToken::new(),
         })
     }
 
-    pub fn download(&self, custom_out_path: Option<&PathBuf>) -> Result<(), SwishError> {
+    /// Rebuild an interrupted upload from its state file, `upload` then only sends what is missing.
+    /// Requests are sent with `CurlTransport`, replace `transport` to use another one
+    pub fn from_upload_state(state_path: &Path) -> Result<Self, SwishError> {
+        let state_file = StateFile::load(state_path)?;
+        let state = state_file.state();
+        let endpoint = Endpoint::new(&state.base_url);
 
-      // Create the directory if it doesn't exist or use the current directory
-        let out_path = match custom_out_path {
-            Some(path) => {
-                if !path.exists() {
-                    Some(std::fs::create_dir_all(path)?);
-                }
-                Some(path)
+        let files = state
+            .files
+            .iter()
+            .map(|file| Swissfile::Local(LocalSwissfile::from_state(file, &state, &endpoint)))
+            .collect();
+
+        Ok(Swissfiles {
+            files,
+            container_uuid: state.container_uuid,
+            endpoint,
+            transport: Arc::new(CurlTransport::new()),
+            state_file: Some(state_file),
+            jobs: 1,
+            retry: RetryPolicy::default(),
+            continue_downloads: false,
+            segments: 1,
+            on_conflict: OnConflict::default(),
+            expired_date: None,
+            progress: Arc::new(IndicatifProgress::new()),
+            cancel: CancelToken::new(),
+        })
+    }
+
+    /// Files of a transfer opened with `new_remotefiles`
+    pub fn remote_files(&self) -> impl Iterator<Item = &RemoteSwissfile> {
+        self.files.iter().filter_map(|file| match file {
+            Swissfile::Remote(remote_file) => Some(remote_file),
+            Swissfile::Local(_) => None,
+        })
+    }
+
+    /// Details of the files of a transfer opened with `new_remotefiles`, nothing gets downloaded
+    pub fn info(&self) -> TransferInfo {
+        TransferInfo::new(&self.container_uuid, self.remote_files())
+    }
+
+    /// Details of an upload that gave `download_link`, to be reported to whoever asked for it
+    pub fn upload_report(&self, download_link: &str) -> UploadReport {
+        let files = self
+            .files
+            .iter()
+            .filter_map(|file| match file {
+                Swissfile::Local(local_file) => Some(UploadedFile {
+                    name: local_file.name.clone(),
+                    uuid: local_file.files_uuid.clone(),
+                    size: local_file.size,
+                }),
+                Swissfile::Remote(_) => None,
+            })
+            .collect();
+        UploadReport {
+            download_link: download_link.to_string(),
+            container_uuid: self.container_uuid.clone(),
+            expired_date: self.expired_date.clone(),
+            files,
+        }
+    }
+
+    /// Only keep the files of the transfer `selection` picks, `download` then skips the others
+    pub fn select(&mut self, selection: &FileSelection) -> Result<(), SwishError> {
+        selection.apply(&mut self.files)
+    }
+
+    /// Download every file of the transfer, returns the ones written
+    pub fn download(
+        &self,
+        custom_out_path: Option<&PathBuf>,
+    ) -> Result<Vec<DownloadedFile>, SwishError> {
+        block_on(self.run_download(custom_out_path))
+    }
+
+    /// What `download` runs
+    pub(crate) async fn run_download(
+        &self,
+        custom_out_path: Option<&PathBuf>,
+    ) -> Result<Vec<DownloadedFile>, SwishError> {
+        // Create the directory if it doesn't exist or use the current directory
+        if let Some(path) = custom_out_path {
+            if !path.exists() {
+                std::fs::create_dir_all(path)?;
             }
-            None => None,
+        }
+        let options = DownloadOptions {
+            out_path: custom_out_path.cloned(),
+            continue_partial: self.continue_downloads,
+            segments: self.segments,
+            on_conflict: self.on_conflict,
         };
 
+        let mut remote_files = Vec::new();
         for file in &self.files {
             match file {
                 Swissfile::Local(_) => {
                     // Handle local file download
                     unimplemented!("Humm, Why would you want to download a local file ?")
                 }
-                Swissfile::Remote(remote_swissfile) => {
-                    // Call download method on RemoteSwissfile
-                    remote_swissfile.download(out_path)?;
-                }
+                Swissfile::Remote(remote_swissfile) => remote_files.push(remote_swissfile),
             }
         }
-        Ok(())
+        download_files(
+            &remote_files,
+            self.transport.as_ref(),
+            &options,
+            self.jobs,
+            self.progress.as_ref(),
+            &self.cancel,
+        )
+        .await
     }
 
     pub fn upload(&self) -> Result<String, SwishError> {
+        block_on(self.run_upload())
+    }
+
+    /// What `upload` runs
+    pub(crate) async fn run_upload(&self) -> Result<String, SwishError> {
+        let result = match self.upload_files().await {
+            Ok(()) => self.finalize_upload().await,
+            Err(error) => Err(error),
+        };
+        let download_link = match result {
+            Ok(download_link) => download_link,
+            Err(error) => {
+                if let Some(state_file) = &self.state_file {
+                    log::warn!(
+                        "Upload interrupted, resume it with: swish resume {}",
+                        state_file.path.display()
+                    );
+                }
+                return Err(error);
+            }
+        };
+
+        if let Some(state_file) = &self.state_file {
+            state_file.remove()?;
+        }
+        Ok(download_link)
+    }
+
+    async fn upload_files(&self) -> Result<(), SwishError> {
+        let mut local_files = Vec::new();
         for file in &self.files {
             match file {
-                Swissfile::Local(local_swissfile) => {
-                    // Call upload method on LocalSwissfile
-                    local_swissfile.upload()?;
-                }
+                Swissfile::Local(local_swissfile) => local_files.push(local_swissfile),
                 Swissfile::Remote(_) => {
                     // Handle remote file upload
                     unimplemented!("Humm, Why would you want to upload a remote file ?")
                 }
             }
         }
-
-        let download_link = self.finalize_upload()?;
-        Ok(download_link)
+        upload_chunks(
+            &local_files,
+            self.transport.as_ref(),
+            self.jobs,
+            &self.retry,
+            self.state_file.as_ref(),
+            self.progress.as_ref(),
+            &self.cancel,
+        )
+        .await
     }
 
-    fn finalize_upload(&self) -> Result<String, SwishError> {
-        let url = format!("{}/uploadComplete", SWISSTRANSFER_API);
-        let body = json!({
-            "UUID": self.container_uuid,
-            "lang": "en_GB"
-        })
-        .to_string()
-        .into_bytes();
-        let response = post(&url, body, None)?;
+    async fn finalize_upload(&self) -> Result<String, SwishError> {
+        let url = self.endpoint.api("uploadComplete");
+        let body = UploadCompleteRequest {
+            uuid: self.container_uuid.clone(),
+            lang: "en_GB".to_string(),
+        };
+        let response = self
+            .transport
+            .post(&url, serde_json::to_vec(&body)?, None)
+            .await?;
 
-        Ok(create_download_link(&response)?)
+        create_download_link(&response, &self.endpoint)
     }
 }
 
 impl fmt::Display for Swissfiles {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for file in &self.files {
-            write!(f, "{}\n", file)?;
+            writeln!(f, "{}", file)?;
         }
         Ok(())
     }
 }
 
-fn get_container(
-    path: &PathBuf,
+async fn get_container(
+    files: &[LocalEntry],
     upload_parameter: &UploadParameters,
-) -> Result<serde_json::Value, SwishError> {
-    let url = format!("{}/containers", SWISSTRANSFER_API);
-
-    //check if the path is a file or a folder
-    let files = if path.is_dir() {
-        std::fs::read_dir(path)
-            .unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect::<Vec<_>>()
-    } else {
-        vec![path.clone()]
-    };
+    endpoint: &Endpoint,
+    transport: &dyn Transport,
+) -> Result<ContainerResponse, SwishError> {
+    let url = endpoint.api("containers");
 
     let files: Vec<_> = files
         .iter()
-        .map(|file| {
-            json!({
-                "name": file.file_name().unwrap().to_str().unwrap(),
-                "size": file.metadata().unwrap().len()
-            })
+        .map(|file| ContainerFile {
+            name: file.name.clone(),
+            size: file.size,
         })
         .collect();
 
-    let files_string = serde_json::to_string(&files).unwrap();
-
-    let payload = json!({
-    "duration": upload_parameter.duration,
-    "authorEmail": upload_parameter.author_email,
-    "password": upload_parameter.password,
-    "message": upload_parameter.message,
-    "sizeOfUpload": files.iter().map(|file| file["size"].as_u64().unwrap()).sum::<u64>(),
-    "numberOfDownload": upload_parameter.number_of_download,
-    "numberOfFile": files.len(),
-    "lang": upload_parameter.lang,
-    "recaptcha": "nope",
-    "files": files_string,
-    "recipientsEmails": "[]" // We might want to add this feature later seems pretty useless for my use case
-        });
-
-    let payload_string = serde_json::to_string(&payload).unwrap();
-    let payload_bytes = payload_string.as_bytes();
+    let payload = ContainerRequest {
+        duration: upload_parameter.duration,
+        author_email: upload_parameter.author_email.clone(),
+        password: upload_parameter.password.clone(),
+        message: upload_parameter.message.clone(),
+        size_of_upload: files.iter().map(|file| file.size).sum(),
+        number_of_download: upload_parameter.number_of_download,
+        number_of_file: files.len(),
+        lang: upload_parameter.lang.clone(),
+        recaptcha: "nope".to_string(),
+        files: serde_json::to_string(&files)?,
+        // We might want to add this feature later seems pretty useless for my use case
+        recipients_emails: "[]".to_string(),
+    };
 
-    let response = post(url.as_str(), payload_bytes.to_vec(), None)?;
+    let response = transport
+        .post(url.as_str(), serde_json::to_vec(&payload)?, None)
+        .await?;
 
-    Ok(serde_json::from_str(&String::from_utf8(response).unwrap()).unwrap())
+    model::parse(&response)
 }
 
-pub fn create_download_link(response: &Vec<u8>) -> Result<String, SwishError> {
-    //convert u8 to json object
-    let response: serde_json::Value =
-        serde_json::from_str(&String::from_utf8(response.to_vec()).unwrap()).unwrap();
+pub fn create_download_link(response: &[u8], endpoint: &Endpoint) -> Result<String, SwishError> {
+    let response: UploadCompleteResponse = model::parse(response)?;
 
-    let link = format!(
-        "https://www.swisstransfer.com/d/{}",
-        response[0]["linkUUID"].as_str().unwrap()
-    );
+    // Several links are sent back when the transfer is also shared by email, they all work
+    let link = match response.first() {
+        Some(link) => endpoint.download_link(&link.link_uuid),
+        None => {
+            return Err(SwishError::MissingField {
+                path: "/0/linkUUID".to_string(),
+            })
+        }
+    };
 
     Ok(link)
 }
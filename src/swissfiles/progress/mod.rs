@@ -0,0 +1,237 @@
+//! What a transfer tells about how it goes.
+//!
+//! Uploads and downloads report to a [`ProgressObserver`], `IndicatifProgress` unless told
+//! otherwise, which draws progress bars on stderr. Library users can give their own to follow
+//! transfers from their UI, or `NoProgress` to keep the terminal quiet.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::errors::SwishError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ProgressEvent<'a> {
+    /// `files` are about to be transferred, `size` bytes in total of which `done` already were,
+    /// e.g. by the upload being resumed
+    TransferStarted {
+        direction: Direction,
+        files: usize,
+        size: u64,
+        done: u64,
+    },
+    /// `file`, `size` bytes long, starts being transferred
+    FileStarted { file: &'a str, size: u64 },
+    /// `bytes` more of `file` went through
+    BytesTransferred { file: &'a str, bytes: u64 },
+    /// `bytes` of `file` already reported will go through again, e.g. a failed chunk or a
+    /// download starting over
+    BytesRewound { file: &'a str, bytes: u64 },
+    /// The server accepted the chunk `chunk` of `file`, `size` bytes long
+    ChunkSent {
+        file: &'a str,
+        chunk: usize,
+        size: u64,
+    },
+    /// Attempt `attempt` of `max_attempts` at sending chunk `chunk` of `file` failed because of
+    /// `reason`, the next one starts after `delay`
+    Retrying {
+        file: &'a str,
+        chunk: usize,
+        attempt: u32,
+        max_attempts: u32,
+        delay: Duration,
+        reason: &'a str,
+    },
+    /// `file` is complete, or was `skipped` as it is already there
+    FileCompleted { file: &'a str, skipped: bool },
+    /// `file` could not be transferred
+    FileFailed {
+        file: &'a str,
+        error: &'a SwishError,
+    },
+    /// The transfer is over, `error` is why it failed if it did
+    Finished { error: Option<&'a SwishError> },
+}
+
+/// Told about every step of a transfer, from any of the threads working on it
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: ProgressEvent);
+}
+
+/// Ignores every event
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_event(&self, _: ProgressEvent) {}
+}
+
+/// Draws progress bars on stderr: a single one for uploads, one per file below one counting
+/// the files for downloads
+#[derive(Default)]
+pub struct IndicatifProgress {
+    bars: Mutex<Bars>,
+}
+
+#[derive(Default)]
+struct Bars {
+    multi: MultiProgress,
+    direction: Option<Direction>,
+    /// Bytes of an upload, files of a download
+    overall: Option<ProgressBar>,
+    /// Bars of the files being downloaded
+    files: HashMap<String, ProgressBar>,
+}
+
+impl Bars {
+    /// Bar the bytes of `file` go to
+    fn bytes_bar(&self, file: &str) -> Option<&ProgressBar> {
+        match self.direction {
+            Some(Direction::Upload) => self.overall.as_ref(),
+            _ => self.files.get(file),
+        }
+    }
+}
+
+impl IndicatifProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressObserver for IndicatifProgress {
+    fn on_event(&self, event: ProgressEvent) {
+        let mut bars = self.bars.lock().unwrap();
+        match event {
+            ProgressEvent::TransferStarted {
+                direction: Direction::Upload,
+                size,
+                done,
+                ..
+            } => {
+                let overall = bars.multi.add(ProgressBar::new(size));
+                overall.set_style(ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})").unwrap()
+                    .progress_chars("#>-"));
+                overall.inc(done);
+                bars.direction = Some(Direction::Upload);
+                bars.overall = Some(overall);
+            }
+            ProgressEvent::TransferStarted {
+                direction: Direction::Download,
+                files,
+                ..
+            } => {
+                let overall = bars.multi.add(ProgressBar::new(files as u64));
+                overall.set_style(ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files").unwrap()
+                    .progress_chars("#>-"));
+                bars.direction = Some(Direction::Download);
+                bars.overall = Some(overall);
+            }
+            ProgressEvent::FileStarted { file, size } => {
+                if bars.direction != Some(Direction::Download) {
+                    return;
+                }
+                let progress_bar = bars.multi.add(ProgressBar::new(size));
+                progress_bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("  [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+                progress_bar.set_message(file.to_string());
+                bars.files.insert(file.to_string(), progress_bar);
+            }
+            ProgressEvent::BytesTransferred { file, bytes } => {
+                if let Some(progress_bar) = bars.bytes_bar(file) {
+                    progress_bar.inc(bytes);
+                }
+            }
+            ProgressEvent::BytesRewound { file, bytes } => {
+                if let Some(progress_bar) = bars.bytes_bar(file) {
+                    progress_bar.set_position(progress_bar.position().saturating_sub(bytes));
+                }
+            }
+            ProgressEvent::FileCompleted { file, .. } | ProgressEvent::FileFailed { file, .. } => {
+                if let Some(progress_bar) = bars.files.remove(file) {
+                    progress_bar.finish_and_clear();
+                    if let Some(overall) = &bars.overall {
+                        overall.inc(1);
+                    }
+                }
+            }
+            ProgressEvent::Finished { .. } => {
+                if let Some(overall) = bars.overall.take() {
+                    overall.finish();
+                }
+            }
+            ProgressEvent::ChunkSent { .. } | ProgressEvent::Retrying { .. } => (),
+        }
+    }
+}
+
+/// Reports the bytes of a single file, keeping count of them so they can be taken back
+pub struct FileProgress<'a> {
+    observer: &'a dyn ProgressObserver,
+    file: &'a str,
+    reported: AtomicU64,
+}
+
+impl<'a> FileProgress<'a> {
+    pub fn new(observer: &'a dyn ProgressObserver, file: &'a str) -> Self {
+        Self {
+            observer,
+            file,
+            reported: AtomicU64::new(0),
+        }
+    }
+
+    /// `bytes` more went through
+    pub fn advance(&self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        self.reported.fetch_add(bytes, Ordering::SeqCst);
+        self.observer.on_event(ProgressEvent::BytesTransferred {
+            file: self.file,
+            bytes,
+        });
+    }
+
+    /// `bytes` of what was reported will go through again
+    pub fn rewind(&self, bytes: u64) {
+        let reported = self
+            .reported
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |reported| {
+                Some(reported.saturating_sub(bytes))
+            })
+            .unwrap();
+        let bytes = bytes.min(reported);
+        if bytes > 0 {
+            self.observer.on_event(ProgressEvent::BytesRewound {
+                file: self.file,
+                bytes,
+            });
+        }
+    }
+
+    /// Everything reported will go through again
+    pub fn reset(&self) {
+        self.rewind(u64::MAX);
+    }
+
+    /// Any other event, about this file or not
+    pub fn report(&self, event: ProgressEvent) {
+        self.observer.on_event(event);
+    }
+}
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// What an upload produced, see `Swissfiles::upload_report`
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadReport {
+    pub download_link: String,
+    pub container_uuid: String,
+    /// When the transfer expires, if the server said so
+    pub expired_date: Option<String>,
+    pub files: Vec<UploadedFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadedFile {
+    pub name: String,
+    pub uuid: String,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// A file written by `Swissfiles::download`
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedFile {
+    /// Name of the file in the transfer
+    pub name: String,
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+}
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use futures_util::future::join_all;
+use tokio::sync::Notify;
+
+use crate::api::chunks::Chunk;
+use crate::api::retry::RetryPolicy;
+use crate::api::transport::Transport;
+use crate::cancel::CancelToken;
+use crate::errors::SwishError;
+
+use super::progress::{Direction, FileProgress, ProgressEvent, ProgressObserver};
+use super::report::DownloadedFile;
+use super::swissfile::{DownloadOptions, LocalSwissfile, RemoteSwissfile};
+use super::uploadstate::StateFile;
+
+/// A chunk waiting to be sent
+struct Job<'a> {
+    file: usize,
+    chunk: &'a Chunk,
+}
+
+struct Queue<'a> {
+    /// Chunks that can be sent right away
+    pending: VecDeque<Job<'a>>,
+    /// Last chunk of each file, it tells the server the file is complete
+    /// so it is only released once every other chunk of the file has been accepted
+    held_back: Vec<Option<Job<'a>>>,
+    /// Chunks of each file, the last one excepted, not accepted yet
+    remaining: Vec<usize>,
+    /// Whether a chunk of each file has been taken already
+    started: Vec<bool>,
+    in_flight: usize,
+    error: Option<SwishError>,
+}
+
+impl<'a> Queue<'a> {
+    fn new(files: &'a [&'a LocalSwissfile], state_file: Option<&StateFile>) -> (Self, u64) {
+        let mut queue = Queue {
+            pending: VecDeque::new(),
+            held_back: Vec::new(),
+            remaining: Vec::new(),
+            started: vec![false; files.len()],
+            in_flight: 0,
+            error: None,
+        };
+        let mut already_uploaded = 0;
+
+        for (index, file) in files.iter().enumerate() {
+            let mut last = None;
+            let mut remaining = 0;
+            for chunk in &file.chunks {
+                let completed = state_file
+                    .map(|state_file| state_file.is_completed(&file.files_uuid, chunk.index))
+                    .unwrap_or(false);
+                if completed {
+                    log::debug!("Chunk {} of {} already uploaded", chunk.index, file.name);
+                    already_uploaded += chunk.size as u64;
+                } else if file.is_last_chunk(chunk) {
+                    last = Some(Job { file: index, chunk });
+                } else {
+                    remaining += 1;
+                    queue.pending.push_back(Job { file: index, chunk });
+                }
+            }
+            queue.held_back.push(None);
+            queue.remaining.push(remaining);
+            match (remaining, last) {
+                (0, Some(last)) => queue.pending.push_back(last),
+                (_, last) => queue.held_back[index] = last,
+            }
+        }
+        (queue, already_uploaded)
+    }
+
+    fn accepted(&mut self, job: &Job) {
+        if self.held_back[job.file].is_none() {
+            return;
+        }
+        self.remaining[job.file] -= 1;
+        if self.remaining[job.file] == 0 {
+            // Send it as soon as possible so the file gets completed
+            if let Some(last) = self.held_back[job.file].take() {
+                self.pending.push_front(last);
+            }
+        }
+    }
+}
+
+/// Upload the chunks of every file over `transport`, `jobs` at once, each chunk is retried following `retry`.
+/// Chunks accepted by the server are recorded in `state_file` and skipped if already there.
+/// Every step is reported to `observer`, once `cancel` is cancelled no other chunk is sent.
+pub async fn upload_chunks(
+    files: &[&LocalSwissfile],
+    transport: &dyn Transport,
+    jobs: usize,
+    retry: &RetryPolicy,
+    state_file: Option<&StateFile>,
+    observer: &dyn ProgressObserver,
+    cancel: &CancelToken,
+) -> Result<(), SwishError> {
+    let (queue, already_uploaded) = Queue::new(files, state_file);
+    observer.on_event(ProgressEvent::TransferStarted {
+        direction: Direction::Upload,
+        files: files.len(),
+        size: files.iter().map(|file| file.size).sum(),
+        done: already_uploaded,
+    });
+
+    let queue = &Mutex::new(queue);
+    let wakeup = &Notify::new();
+
+    join_all((0..jobs.max(1)).map(|_| async move {
+        loop {
+            let (job, first) = loop {
+                // Created before looking at the queue so that no wake up is missed
+                let notified = wakeup.notified();
+                {
+                    let mut queue = queue.lock().unwrap();
+                    if queue.error.is_some() {
+                        return;
+                    }
+                    if cancel.is_cancelled() {
+                        queue.error = Some(SwishError::Interrupted);
+                        wakeup.notify_waiters();
+                        return;
+                    }
+                    if let Some(job) = queue.pending.pop_front() {
+                        queue.in_flight += 1;
+                        let first = !std::mem::replace(&mut queue.started[job.file], true);
+                        break (job, first);
+                    }
+                    if queue.in_flight == 0 {
+                        return;
+                    }
+                }
+                notified.await;
+            };
+
+            let file = files[job.file];
+            let progress = FileProgress::new(observer, &file.name);
+            if first {
+                progress.report(ProgressEvent::FileStarted {
+                    file: &file.name,
+                    size: file.size,
+                });
+            }
+            let result = file
+                .upload_chunk(transport, job.chunk, retry, &progress, cancel)
+                .await
+                .and_then(|checksum| match state_file {
+                    Some(state_file) => {
+                        state_file.complete_chunk(&file.files_uuid, job.chunk.index, checksum)
+                    }
+                    None => Ok(()),
+                });
+
+            match &result {
+                Ok(()) => {
+                    progress.report(ProgressEvent::ChunkSent {
+                        file: &file.name,
+                        chunk: job.chunk.index,
+                        size: job.chunk.size as u64,
+                    });
+                    if file.is_last_chunk(job.chunk) {
+                        progress.report(ProgressEvent::FileCompleted {
+                            file: &file.name,
+                            skipped: false,
+                        });
+                    }
+                }
+                Err(error) => progress.report(ProgressEvent::FileFailed {
+                    file: &file.name,
+                    error,
+                }),
+            }
+
+            let mut queue = queue.lock().unwrap();
+            queue.in_flight -= 1;
+            match result {
+                Ok(()) => queue.accepted(&job),
+                Err(error) => {
+                    if queue.error.is_none() {
+                        queue.error = Some(error);
+                    }
+                }
+            }
+            wakeup.notify_waiters();
+        }
+    }))
+    .await;
+
+    let error = queue.lock().unwrap().error.take();
+    observer.on_event(ProgressEvent::Finished {
+        error: error.as_ref(),
+    });
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Download `files` over `transport` as `options` say, `jobs` of them at once.
+/// A failed file does not stop the others, every failure is reported at the end.
+/// Every step is reported to `observer`.
+/// Returns the files written, in the order of the transfer
+pub async fn download_files(
+    files: &[&RemoteSwissfile],
+    transport: &dyn Transport,
+    options: &DownloadOptions,
+    jobs: usize,
+    observer: &dyn ProgressObserver,
+    cancel: &CancelToken,
+) -> Result<Vec<DownloadedFile>, SwishError> {
+    observer.on_event(ProgressEvent::TransferStarted {
+        direction: Direction::Download,
+        files: files.len(),
+        size: files.iter().map(|file| file.size).sum(),
+        done: 0,
+    });
+    let next = AtomicUsize::new(0);
+    let written = Mutex::new(Vec::new());
+    let errors = Mutex::new(Vec::new());
+
+    {
+        let (next, written, errors) = (&next, &written, &errors);
+        join_all((0..jobs.clamp(1, files.len().max(1))).map(|_| async move {
+            loop {
+                // Once cancelled there is no point starting other files
+                if cancel.is_cancelled() {
+                    return;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let file = match files.get(index) {
+                    Some(file) => file,
+                    None => return,
+                };
+
+                let progress = FileProgress::new(observer, &file.name);
+                progress.report(ProgressEvent::FileStarted {
+                    file: &file.name,
+                    size: file.size,
+                });
+                let result = file.download(transport, options, &progress, cancel).await;
+                progress.report(match &result {
+                    Ok(path) => ProgressEvent::FileCompleted {
+                        file: &file.name,
+                        skipped: path.is_none(),
+                    },
+                    Err(error) => ProgressEvent::FileFailed {
+                        file: &file.name,
+                        error,
+                    },
+                });
+
+                match result {
+                    Ok(Some(path)) => written.lock().unwrap().push((index, path)),
+                    Ok(None) => (),
+                    Err(error) => {
+                        log::error!("Failed to download {}: {}", file.name, error);
+                        errors.lock().unwrap().push((index, error));
+                    }
+                }
+            }
+        }))
+        .await;
+    }
+
+    let result = match cancel.is_cancelled() {
+        true => Err(SwishError::Interrupted),
+        false => downloaded(files, written, errors),
+    };
+    observer.on_event(ProgressEvent::Finished {
+        error: result.as_ref().err(),
+    });
+    result
+}
+
+/// What `download_files` returns once every file has been tried
+fn downloaded(
+    files: &[&RemoteSwissfile],
+    written: Mutex<Vec<(usize, PathBuf)>>,
+    errors: Mutex<Vec<(usize, SwishError)>>,
+) -> Result<Vec<DownloadedFile>, SwishError> {
+    let mut errors = errors.into_inner().unwrap();
+    match errors.len() {
+        0 => {
+            let mut written = written.into_inner().unwrap();
+            written.sort_by_key(|(index, _)| *index);
+            Ok(written
+                .into_iter()
+                .map(|(index, path)| DownloadedFile {
+                    name: files[index].name.clone(),
+                    path,
+                    size: files[index].size,
+                })
+                .collect())
+        }
+        1 => Err(errors.remove(0).1),
+        _ => {
+            // Report them in the order of the transfer, whatever order they failed in
+            errors.sort_by_key(|(index, _)| *index);
+            let errors = errors
+                .into_iter()
+                .map(|(index, error)| (files[index].name.clone(), error))
+                .collect();
+            Err(SwishError::DownloadsFailed { errors })
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use globset::GlobSet;
+
+use crate::errors::SwishError;
+use crate::swissfiles::filecollector::{build_globset, glob_matches};
+use crate::swissfiles::swissfile::Swissfile;
+
+/// Which files of a transfer get downloaded, everything by default
+#[derive(Debug, Clone, Default)]
+pub struct FileSelection {
+    /// Glob patterns, if any is given only the files matching one of them (or `file_uuids`) are downloaded
+    pub only: Vec<String>,
+    /// Glob patterns of the files to leave out, even if `only` or `file_uuids` picked them
+    pub exclude: Vec<String>,
+    /// UUIDs of files to download, if any is given only them (or the files matching `only`) are downloaded
+    pub file_uuids: Vec<String>,
+}
+
+impl FileSelection {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty() && self.file_uuids.is_empty()
+    }
+
+    /// Drop the remote files of `files` the selection does not pick.
+    /// Globs are matched like upload filters, so they also match the files of the folders they match
+    pub(crate) fn apply(&self, files: &mut Vec<Swissfile>) -> Result<(), SwishError> {
+        let only = build_globset(&self.only)?;
+        let exclude = build_globset(&self.exclude)?;
+        let picks_all = self.only.is_empty() && self.file_uuids.is_empty();
+
+        for uuid in &self.file_uuids {
+            let known = files.iter().any(|file| match file {
+                Swissfile::Remote(remote_file) => &remote_file.uuid == uuid,
+                Swissfile::Local(_) => false,
+            });
+            if !known {
+                log::warn!("No file of the transfer has the UUID {}", uuid);
+            }
+        }
+
+        files.retain(|file| match file {
+            Swissfile::Remote(remote_file) => {
+                let picked = picks_all
+                    || self.file_uuids.contains(&remote_file.uuid)
+                    || path_matches(&only, &remote_file.name);
+                picked && !path_matches(&exclude, &remote_file.name)
+            }
+            Swissfile::Local(_) => true,
+        });
+
+        if files.is_empty() {
+            return Err(SwishError::NothingToDownload);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the globs match the file `name` or one of the folders it is in
+fn path_matches(globs: &GlobSet, name: &str) -> bool {
+    let folders = name.match_indices('/').map(|(index, _)| &name[..index]);
+    folders.chain([name]).any(|path| glob_matches(globs, path))
+}
@@ -1,22 +1,49 @@
-use crate::api::chunks::{build_chunks_array, Chunk};
+use crate::api::chunks::{build_chunks_array, Checksummed, Chunk};
+use crate::api::model::{self, ContainerResponse, DownloadToken, DownloadTokenRequest, FileEntry};
+use crate::cancel::CancelToken;
+use crate::swissfiles::conflict::OnConflict;
+use crate::swissfiles::filecollector::LocalEntry;
+use crate::swissfiles::filename;
+use crate::swissfiles::progress::{FileProgress, ProgressEvent};
+use crate::swissfiles::uploadstate::{FileState, UploadState};
 use crate::{
-    api::{new_easy2_download, new_easy2_upload, post},
+    api::endpoint::Endpoint,
+    api::retry::{is_retryable_error, is_retryable_status, RetryPolicy},
+    api::transport::{Body, Request, Sink, Transport},
     errors::SwishError,
 };
-use serde_json::json;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use futures_util::future::join_all;
 use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-const SWISSTRANSFER_API: &str = "https://www.swisstransfer.com/api";
-const CHUNK_SIZE: usize = 52428800;
+/// Appended to the name of a file while it is being downloaded
+pub const PART_EXTENSION: &str = ".swish-part";
 
+/// Ranges of a segmented download are never smaller, below it a new connection is not worth it
+pub const MIN_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// How the files of a transfer are downloaded, the same for every file
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    /// Directory the files are written under, the current directory if None
+    pub out_path: Option<PathBuf>,
+    /// Complete partially downloaded files instead of downloading them again
+    pub continue_partial: bool,
+    /// Number of connections a large file is downloaded over
+    pub segments: usize,
+    /// What happens to files already in the output directory
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Clone)]
 pub enum Swissfile {
     Local(LocalSwissfile),
     Remote(RemoteSwissfile),
 }
 
+#[derive(Clone)]
 pub struct LocalSwissfile {
     pub path: std::path::PathBuf,
     pub name: String,
@@ -25,6 +52,7 @@ pub struct LocalSwissfile {
     pub container_uuid: String,
     pub files_uuid: String,
     pub chunks: Vec<Chunk>,
+    pub endpoint: Endpoint,
 }
 
 impl fmt::Display for LocalSwissfile {
@@ -34,17 +62,30 @@ impl fmt::Display for LocalSwissfile {
 }
 
 impl LocalSwissfile {
-    pub fn new(path: std::path::PathBuf, container: &serde_json::Value) -> Self {
-        let path = path.clone();
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        let size = path.metadata().unwrap().len();
-        let chunks = build_chunks_array(size as usize, CHUNK_SIZE);
-        let container_uuid = container["container"]["UUID"].as_str().unwrap().to_string();
-        let files_uuid = container["filesUUID"][0].as_str().unwrap().to_string();
-        let upload_host = container["uploadHost"].as_str().unwrap().to_string();
+    /// `index` is the position of the file in the `files` payload sent to create the container,
+    /// the server answers with the files UUIDs in that same order
+    pub fn new(
+        entry: LocalEntry,
+        container: &ContainerResponse,
+        index: usize,
+        chunk_size: usize,
+        endpoint: &Endpoint,
+    ) -> Result<Self, SwishError> {
+        let LocalEntry { path, name, size } = entry;
+        let chunks = build_chunks_array(size as usize, chunk_size);
+        let container_uuid = container.container.uuid.clone();
+        let files_uuid = match container.files_uuid.get(index) {
+            Some(files_uuid) => files_uuid.clone(),
+            None => {
+                return Err(SwishError::MissingField {
+                    path: format!("/filesUUID/{}", index),
+                })
+            }
+        };
+        let upload_host = container.upload_host.clone();
 
         // we miglht need to check if the file exists here idk
-        Self {
+        Ok(Self {
             path,
             name,
             size,
@@ -52,51 +93,173 @@ impl LocalSwissfile {
             container_uuid,
             files_uuid,
             chunks,
+            endpoint: endpoint.clone(),
+        })
+    }
+
+    /// Rebuild a file of an interrupted upload
+    pub fn from_state(file: &FileState, state: &UploadState, endpoint: &Endpoint) -> Self {
+        Self {
+            path: file.path.clone(),
+            name: file.name.clone(),
+            size: file.size,
+            upload_host: state.upload_host.clone(),
+            container_uuid: state.container_uuid.clone(),
+            files_uuid: file.file_uuid.clone(),
+            chunks: build_chunks_array(file.size as usize, state.chunk_size),
+            endpoint: endpoint.clone(),
         }
     }
 
-    pub fn upload(&self) -> Result<(), SwishError> {
-        let file = File::open(&self.path)?;
-        let mut easy2 = new_easy2_upload("".to_string(), None, self.size as usize, &file)?; // Pass a reference to file
+    /// Send a single chunk over `transport`, what is sent is reported to `progress`.
+    /// Exactly the bytes of the chunk are streamed from disk, nothing is loaded in memory.
+    /// Network errors, 429 and 5xx answers are retried following `retry`, unless `cancel` is
+    /// cancelled which also aborts the attempt in flight.
+    /// Returns the SHA-256 of the chunk accepted by the server, every attempt must have read the
+    /// same bytes from disk
+    pub async fn upload_chunk(
+        &self,
+        transport: &dyn Transport,
+        chunk: &Chunk,
+        retry: &RetryPolicy,
+        progress: &FileProgress<'_>,
+        cancel: &CancelToken,
+    ) -> Result<String, SwishError> {
+        let mut attempt = 1;
+        // Of the first attempt that read the whole chunk
+        let mut checksum = None;
+        loop {
+            let mut sent = 0;
+            let result = self
+                .send_chunk(transport, chunk, progress, cancel, &mut sent)
+                .await;
+            if cancel.is_cancelled() {
+                progress.rewind(sent);
+                return Err(SwishError::Interrupted);
+            }
+            if let Ok((_, Some(read))) = &result {
+                if checksum.get_or_insert_with(|| read.clone()) != read {
+                    return Err(self.chunk_failed(
+                        chunk,
+                        attempt,
+                        "the file changed while it was being uploaded".to_string(),
+                    ));
+                }
+            }
+
+            let reason = match result {
+                Ok((200..=299, Some(read))) => return Ok(read),
+                // The transport did not read the body, e.g. it answers from memory
+                Ok((200..=299, None)) => return Ok(chunk.sha256(&self.path)?),
+                Ok((response_code, _)) if is_retryable_status(response_code) => {
+                    format!("answered with {}", response_code)
+                }
+                Ok((response_code, _)) => {
+                    return Err(self.chunk_failed(
+                        chunk,
+                        attempt,
+                        format!("answered with {}", response_code),
+                    ))
+                }
+                Err(SwishError::CurlError { error }) if is_retryable_error(&error) => {
+                    error.to_string()
+                }
+                Err(error) => return Err(error),
+            };
+
+            // What was sent will be sent again, it must not be counted twice
+            progress.rewind(sent);
 
-        // Iterate over a reference to chunks to avoid moving it
-        for chunk in &self.chunks {
-            let mut file = File::open(&self.path)?;
-            file.seek(SeekFrom::Start(chunk.offset as u64))?;
-            let mut buffer = vec![0; chunk.size];
-            file.read_exact(&mut buffer)?;
-            let upload_url = self.build_chunked_upload_url(&chunk);
-            easy2.url(&upload_url)?;
-            easy2.post(true)?;
-            easy2.post_field_size(chunk.size as u64)?;
-            easy2.perform()?;
+            if attempt >= retry.max_attempts {
+                return Err(self.chunk_failed(chunk, attempt, reason));
+            }
+            let delay = retry.delay(attempt);
+            log::warn!(
+                "Chunk {} of {} failed ({}), retrying in {:?} ({}/{})",
+                chunk.index,
+                self.name,
+                reason,
+                delay,
+                attempt,
+                retry.max_attempts
+            );
+            progress.report(ProgressEvent::Retrying {
+                file: &self.name,
+                chunk: chunk.index,
+                attempt,
+                max_attempts: retry.max_attempts,
+                delay,
+                reason: &reason,
+            });
+            cancel.sleep(delay).await?;
+            attempt += 1;
         }
-        Ok(())
+    }
+
+    /// One attempt at sending a chunk, returns the response code of the server and the SHA-256
+    /// of the chunk if it was read in full.
+    /// `sent` is how much of the chunk has been reported to `progress`
+    async fn send_chunk(
+        &self,
+        transport: &dyn Transport,
+        chunk: &Chunk,
+        progress: &FileProgress<'_>,
+        cancel: &CancelToken,
+        sent: &mut u64,
+    ) -> Result<(u32, Option<String>), SwishError> {
+        let mut reader = Checksummed::new(chunk.reader(&self.path)?);
+        let body = Body::Stream {
+            reader: &mut reader,
+            size: chunk.size as u64,
+        };
+        let request = Request::post(&self.build_chunked_upload_url(chunk), body);
+
+        let response_code = transport
+            .send(request, &mut Vec::new(), &mut |uploaded, _| {
+                progress.advance(uploaded.saturating_sub(*sent));
+                *sent = (*sent).max(uploaded);
+                !cancel.is_cancelled()
+            })
+            .await?;
+        let checksum = (reader.read == chunk.size as u64).then(|| reader.finish());
+        Ok((response_code, checksum))
+    }
+
+    fn chunk_failed(&self, chunk: &Chunk, attempts: u32, reason: String) -> SwishError {
+        SwishError::ChunkUploadFailed {
+            file: self.name.clone(),
+            chunk: chunk.index,
+            attempts,
+            reason,
+        }
+    }
+
+    pub fn is_last_chunk(&self, chunk: &Chunk) -> bool {
+        chunk.index == self.chunks.len() - 1
     }
 
     fn build_chunked_upload_url(&self, chunk: &Chunk) -> String {
-        format!(
-            "https://{}/api/uploadChunk/{}/{}/{}/{}",
-            self.upload_host,
-            self.container_uuid,
-            self.files_uuid,
-            chunk.index,
-            if chunk.index == self.chunks.len() - 1 {
-                "1"
-            } else {
-                "0"
-            }
+        self.endpoint.host_api(
+            &self.upload_host,
+            &format!(
+                "uploadChunk/{}/{}/{}/{}",
+                self.container_uuid,
+                self.files_uuid,
+                chunk.index,
+                if self.is_last_chunk(chunk) { "1" } else { "0" }
+            ),
         )
     }
 }
 
+#[derive(Clone)]
 pub struct RemoteSwissfile {
     pub name: String,
     pub size: u64,
     pub url: String,
     pub created_date: String,
     pub expired_date: String,
-    pub deleted_date: String,
+    pub deleted_date: Option<String>,
     pub download_counter: u64,
     pub e_virus_scan: String,
     pub mime_type: String,
@@ -104,6 +267,7 @@ pub struct RemoteSwissfile {
     pub download_base_url: String,
     pub container_uuid: String,
     pub password: Option<String>,
+    pub endpoint: Endpoint,
 }
 
 impl fmt::Display for RemoteSwissfile {
@@ -117,96 +281,372 @@ impl fmt::Display for RemoteSwissfile {
 }
 
 impl RemoteSwissfile {
-    pub fn new(
-        json: &serde_json::Value,
+    /// `entry` is the file as listed by the server, its informative fields are left empty when missing
+    pub async fn new(
+        entry: FileEntry,
         download_base_url: &str,
         container_uuid: &str,
         password: Option<&str>,
-    ) -> Self {
+        endpoint: &Endpoint,
+        transport: &dyn Transport,
+    ) -> Result<Self, SwishError> {
         let container_uuid = container_uuid.to_string();
-        let uuid = json["UUID"].as_str().unwrap().to_string();
+        let uuid = entry.uuid;
 
         // If file is password protected, generate a download token and build the URL accordingly
         let url = match password {
-            Some(ref password) => {
-                let token =
-                    RemoteSwissfile::generate_download_token(password, &container_uuid, &uuid)
-                        .unwrap();
-                let token: String =
-                    serde_json::from_str(token.as_str()).unwrap_or_else(|_| token.to_string());
+            Some(password) => {
+                let token = RemoteSwissfile::generate_download_token(
+                    password,
+                    &container_uuid,
+                    &uuid,
+                    endpoint,
+                    transport,
+                )
+                .await?;
                 format!("{}/{}?token={}", download_base_url, uuid, token)
             }
-            None => format!(
-                "{}/{}",
-                download_base_url,
-                json["UUID"].as_str().unwrap().to_string()
-            ),
+            None => format!("{}/{}", download_base_url, uuid),
         };
 
-        Self {
-            name: json["fileName"].as_str().unwrap().to_string(),
-            size: json["fileSizeInBytes"].as_u64().unwrap(),
+        Ok(Self {
+            name: entry.file_name,
+            size: entry.file_size_in_bytes,
             url,
-            created_date: json["createdDate"].as_str().unwrap().to_string(),
-            expired_date: json["expiredDate"].as_str().unwrap().to_string(),
-            deleted_date: "test".to_owned(), // json["deletedDate"].as_str().unwrap().to_string(),
-            download_counter: json["downloadCounter"].as_u64().unwrap(),
-            e_virus_scan: json["eVirus"].as_str().unwrap().to_string(),
-            mime_type: json["mimeType"].as_str().unwrap().to_string(),
+            created_date: entry.created_date.unwrap_or_default(),
+            expired_date: entry.expired_date.unwrap_or_default(),
+            deleted_date: entry.deleted_date,
+            download_counter: entry.download_counter.unwrap_or_default(),
+            e_virus_scan: entry.e_virus.unwrap_or_default(),
+            mime_type: entry.mime_type.unwrap_or_default(),
             uuid,
             download_base_url: download_base_url.to_string(),
             container_uuid,
             password: password.map(|s| s.to_string()),
-        }
+            endpoint: endpoint.clone(),
+        })
     }
 
-    fn generate_download_token(
+    async fn generate_download_token(
         password: &str,
         container_uuid: &str,
         file_uuid: &str,
+        endpoint: &Endpoint,
+        transport: &dyn Transport,
     ) -> Result<String, SwishError> {
         log::debug!("Generating download token for file: {}", file_uuid);
-        let url = format!("{}/generateDownloadToken", SWISSTRANSFER_API);
-        let payload = json!({
-            "password": password,
-            "containerUUID": container_uuid,
-            "fileUUID": file_uuid,
-        });
+        let url = endpoint.api("generateDownloadToken");
+        let payload = DownloadTokenRequest {
+            password: password.to_string(),
+            container_uuid: container_uuid.to_string(),
+            file_uuid: file_uuid.to_string(),
+        };
 
-        let response = post(url.as_str(), payload.to_string().into_bytes(), None)?;
-        let token: String = String::from_utf8(response).unwrap();
+        let response = transport
+            .post(url.as_str(), serde_json::to_vec(&payload)?, None)
+            .await?;
+        // The token is expected as a JSON string, take the body as is otherwise
+        let token = match model::parse::<DownloadToken>(&response) {
+            Ok(DownloadToken(token)) => token,
+            Err(_) => String::from_utf8_lossy(&response).to_string(),
+        };
 
         log::debug!("Retrieved Token : {:?}", token);
 
         Ok(token)
     }
 
-    pub fn download(&self, custom_out_path: Option<&PathBuf>) -> Result<(), SwishError> {
+    /// Download the file under `options.out_path`, or the current directory.
+    /// With `options.continue_partial` a file smaller than expected is completed instead of being downloaded again.
+    /// Large files are fetched over up to `options.segments` connections at once, a range each.
+    /// `options.on_conflict` decides what happens if a file with the same name is already there.
+    /// Once `cancel` is cancelled the download stops and its part file is removed.
+    /// Returns where the file has been written, None if it has been skipped
+    pub async fn download(
+        &self,
+        transport: &dyn Transport,
+        options: &DownloadOptions,
+        progress: &FileProgress<'_>,
+        cancel: &CancelToken,
+    ) -> Result<Option<PathBuf>, SwishError> {
         log::debug!("Downloading {} from {}", self.name, self.url.clone());
+        let continue_partial = options.continue_partial;
         // Dereference the PathBuf if it exists
-        let out_path = match custom_out_path {
-            Some(path) => path.join(&self.name),
-            None => PathBuf::from(".").join(&self.name),
+        let out_path = match &options.out_path {
+            Some(path) => path.clone(),
+            None => PathBuf::from("."),
         };
 
-        let out_path = out_path.to_str().unwrap();
-        let file = std::fs::File::create(&out_path)?;
-        let url = self.url.clone();
+        // Names of files uploaded from a folder are relative paths, rebuild the tree
+        let out_path = out_path.join(filename::safe_path(&self.name)?);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let complete =
+            std::fs::metadata(&out_path).is_ok_and(|metadata| metadata.len() == self.size);
+        if continue_partial && complete {
+            log::info!("{} is already downloaded", self.name);
+            return Ok(None);
+        }
+
+        let out_path = match options.on_conflict.resolve(&out_path, self.size)? {
+            Some(out_path) => out_path,
+            None => {
+                log::info!(
+                    "Skipping {} as {} already exists",
+                    self.name,
+                    out_path.display()
+                );
+                return Ok(None);
+            }
+        };
+
+        // The file only gets its name once it is complete, a failed download can not be mistaken for it
+        let mut part_path = out_path.clone().into_os_string();
+        part_path.push(PART_EXTENSION);
+        let part_path = PathBuf::from(part_path);
+
+        let partial =
+            std::fs::metadata(&part_path).is_ok_and(|metadata| metadata.len() < self.size);
+        // A part file written by segments is never left behind, so it is safe to continue it in one go
+        let segments = self.segments(options.segments);
+        if segments.len() > 1 && !(continue_partial && partial) {
+            if self
+                .download_segments(transport, &part_path, &segments, progress, cancel)
+                .await?
+            {
+                self.complete(&part_path, &out_path)?;
+                return Ok(Some(out_path));
+            }
+            log::warn!(
+                "The server does not support ranges, downloading {} in one go",
+                self.name
+            );
+            progress.reset();
+        }
+
+        let mut offset = 0;
+        let file = match std::fs::metadata(&part_path) {
+            Ok(metadata) if continue_partial && metadata.len() < self.size => {
+                offset = metadata.len();
+                log::info!("Continuing {} from byte {}", self.name, offset);
+                let mut file = OpenOptions::new().write(true).open(&part_path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file
+            }
+            _ => File::create(&part_path)?,
+        };
+        // Only ask for what is missing, the part file starts over if the server sends everything anyway
+        let mut request = Request::get(&self.url);
+        if offset > 0 {
+            request = request.header(format!("Range: bytes={}-", offset));
+        }
+        progress.advance(offset);
+        let mut part_file = PartFile::new(file, progress);
+        part_file.ranged = offset > 0;
+        part_file.restart_if_range_ignored = true;
+        let result = transport
+            .send(request, &mut part_file, &mut |_, _| !cancel.is_cancelled())
+            .await;
 
-        let easy2 = new_easy2_download(url, None, file, self.size)?;
-        easy2.perform()?;
+        if cancel.is_cancelled() {
+            std::fs::remove_file(&part_path)?;
+            return Err(SwishError::Interrupted);
+        }
+        // The connection dropped, what was received is kept to be continued
+        let response_code = result?;
 
-        match easy2.response_code()? {
+        match response_code {
             500 => {
                 // Clean up the file as it is invalid anyway
-                std::fs::remove_file(&out_path)?;
+                std::fs::remove_file(&part_path)?;
 
                 // we are not sure but we can assume that this is the error x)
                 Err(SwishError::DownloadNumberExceeded)
             }
-            _ => Ok(()),
+            200..=299 => {
+                // A dropped connection can look like the end of the body, the file is kept to be continued
+                let received = std::fs::metadata(&part_path)?.len();
+                if received != self.size {
+                    return Err(SwishError::IncompleteDownload {
+                        name: self.name.clone(),
+                        received,
+                        expected: self.size,
+                    });
+                }
+                self.complete(&part_path, &out_path)?;
+                Ok(Some(out_path))
+            }
+            response_code => {
+                // The body is an error page, not a part of the file
+                std::fs::remove_file(&part_path)?;
+                Err(SwishError::InvalidResponse {
+                    response: format!("download of {} answered with {}", self.name, response_code),
+                })
+            }
         }
     }
+
+    /// Ranges the file is downloaded in with `segments` connections, a single one for small files
+    fn segments(&self, segments: usize) -> Vec<Chunk> {
+        let size = self.size as usize;
+        let segment_size = size.div_ceil(segments.max(1)).max(MIN_SEGMENT_SIZE);
+        build_chunks_array(size, segment_size)
+    }
+
+    /// Download every range of `segments` at once, each one written at its offset in the part file.
+    /// Returns false, leaving nothing behind, if the server does not support ranges
+    async fn download_segments(
+        &self,
+        transport: &dyn Transport,
+        part_path: &Path,
+        segments: &[Chunk],
+        progress: &FileProgress<'_>,
+        cancel: &CancelToken,
+    ) -> Result<bool, SwishError> {
+        File::create(part_path)?.set_len(self.size)?;
+
+        let results =
+            join_all(segments.iter().map(|segment| {
+                self.download_segment(transport, part_path, segment, progress, cancel)
+            }))
+            .await;
+
+        let mut received = 0;
+        let mut ranges_supported = true;
+        let mut error = None;
+        for result in results {
+            match result {
+                Ok(Some(bytes)) => received += bytes,
+                Ok(None) => ranges_supported = false,
+                Err(segment_error) => {
+                    error.get_or_insert(segment_error);
+                }
+            }
+        }
+        if cancel.is_cancelled() {
+            error = Some(SwishError::Interrupted);
+        }
+        if received != self.size && error.is_none() && ranges_supported {
+            error = Some(SwishError::IncompleteDownload {
+                name: self.name.clone(),
+                received,
+                expected: self.size,
+            });
+        }
+
+        // The holes of the preallocated file would look like data, it can not be continued
+        if let Some(error) = error {
+            std::fs::remove_file(part_path)?;
+            return Err(error);
+        }
+        if !ranges_supported {
+            std::fs::remove_file(part_path)?;
+        }
+        Ok(ranges_supported)
+    }
+
+    /// Download a single range, returns the number of bytes received or None if the server ignored the range
+    async fn download_segment(
+        &self,
+        transport: &dyn Transport,
+        part_path: &Path,
+        segment: &Chunk,
+        progress: &FileProgress<'_>,
+        cancel: &CancelToken,
+    ) -> Result<Option<u64>, SwishError> {
+        let mut file = OpenOptions::new().write(true).open(part_path)?;
+        file.seek(SeekFrom::Start(segment.offset as u64))?;
+
+        let range = format!(
+            "Range: bytes={}-{}",
+            segment.offset,
+            segment.offset + segment.size - 1
+        );
+        let request = Request::get(&self.url).header(range);
+        let mut part_file = PartFile::new(file, progress);
+        part_file.ranged = true;
+        let result = transport
+            .send(request, &mut part_file, &mut |_, _| !cancel.is_cancelled())
+            .await;
+
+        if part_file.range_ignored {
+            return Ok(None);
+        }
+
+        match result? {
+            206 => Ok(Some(part_file.received)),
+            // we are not sure but we can assume that this is the error x)
+            500 => Err(SwishError::DownloadNumberExceeded),
+            response_code => Err(SwishError::InvalidResponse {
+                response: format!(
+                    "range {} of {} answered with {}",
+                    segment.index, self.name, response_code
+                ),
+            }),
+        }
+    }
+
+    /// Flush the downloaded file to disk and give it its final name
+    fn complete(&self, part_path: &Path, out_path: &Path) -> Result<(), SwishError> {
+        OpenOptions::new().write(true).open(part_path)?.sync_all()?;
+        std::fs::rename(part_path, out_path)?;
+        Ok(())
+    }
+}
+
+/// Writes the body of a download in its part file, at the position the file is at
+struct PartFile<'a> {
+    file: File,
+    progress: &'a FileProgress<'a>,
+    /// Whether a `Range` header is sent, the file is then expected to be at its start
+    ranged: bool,
+    /// When the server ignores the range, write the whole file from its start instead of failing
+    restart_if_range_ignored: bool,
+    /// Set once the server answered the range with the whole file
+    range_ignored: bool,
+    /// Bytes of the body written so far
+    received: u64,
+}
+
+impl<'a> PartFile<'a> {
+    fn new(file: File, progress: &'a FileProgress<'a>) -> Self {
+        Self {
+            file,
+            progress,
+            ranged: false,
+            restart_if_range_ignored: false,
+            range_ignored: false,
+            received: 0,
+        }
+    }
+}
+
+impl Sink for PartFile<'_> {
+    fn status(&mut self, status: u32) -> bool {
+        // A 200 instead of a 206 means the server ignored the range and sends the whole file
+        if self.ranged && status == 200 {
+            self.range_ignored = true;
+            if !self.restart_if_range_ignored {
+                return false;
+            }
+            log::warn!("The server does not support resuming downloads, starting over");
+            if self.file.seek(SeekFrom::Start(0)).is_err() {
+                return false;
+            }
+            self.progress.reset();
+            self.ranged = false;
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(data)?;
+        self.received += data.len() as u64;
+        self.progress.advance(data.len() as u64);
+        Ok(())
+    }
 }
 
 impl fmt::Display for Swissfile {
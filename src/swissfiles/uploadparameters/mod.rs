@@ -1,3 +1,9 @@
+use std::path::PathBuf;
+
+use crate::api::chunks::DEFAULT_CHUNK_SIZE;
+use crate::api::retry::RetryPolicy;
+
+#[derive(Debug, Clone)]
 pub struct UploadParameters {
     pub duration: u64,
     pub author_email: String,
@@ -6,6 +12,24 @@ pub struct UploadParameters {
     pub number_of_download: u16,
     pub lang: String,
     pub recipients_emails: Vec<String>,
+    /// Upload what symlinks point to instead of skipping them
+    pub follow_symlinks: bool,
+    /// Upload files and folders starting with a dot
+    pub include_hidden: bool,
+    /// Glob patterns, if any is given only the files matching one of them are uploaded
+    pub include: Vec<String>,
+    /// Glob patterns of the files and folders to leave out
+    pub exclude: Vec<String>,
+    /// Leave out what `.gitignore` and `.swishignore` files list
+    pub use_ignore_files: bool,
+    /// Folder where the progress of the upload is saved so it can be resumed, see `Swissfiles::from_upload_state`
+    pub state_dir: Option<PathBuf>,
+    /// Size of the chunks files are split in, see `validate_chunk_size` for the accepted range
+    pub chunk_size: usize,
+    /// Number of chunks uploaded at once
+    pub jobs: usize,
+    /// How failed chunk uploads are sent again
+    pub retry: RetryPolicy,
 }
 
 impl Default for UploadParameters {
@@ -18,6 +42,15 @@ impl Default for UploadParameters {
             number_of_download: 250,
             lang: "en_GB".to_owned(),
             recipients_emails: Vec::new(),
+            follow_symlinks: false,
+            include_hidden: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            use_ignore_files: false,
+            state_dir: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            jobs: 1,
+            retry: RetryPolicy::default(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::chunks::{build_chunks_array, validate_chunk_size};
+use crate::errors::SwishError;
+
+/// Everything needed to pick up an interrupted upload where it stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadState {
+    pub base_url: String,
+    pub container_uuid: String,
+    pub upload_host: String,
+    pub chunk_size: usize,
+    pub files: Vec<FileState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileState {
+    /// Absolute path of the file on disk
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub file_uuid: String,
+    /// Indexes of the chunks the server accepted
+    pub completed_chunks: BTreeSet<usize>,
+    /// SHA-256 of the chunks the server accepted, by index
+    #[serde(default)]
+    pub chunk_checksums: BTreeMap<usize, String>,
+}
+
+/// An `UploadState` kept in sync with its file on disk, clones share the same state
+#[derive(Clone)]
+pub struct StateFile {
+    pub path: PathBuf,
+    state: Arc<Mutex<UploadState>>,
+}
+
+impl StateFile {
+    /// Name of the state file of a container, it is unique so uploads never overwrite each other
+    pub fn file_name(container_uuid: &str) -> String {
+        format!("swish-{}.state", container_uuid)
+    }
+
+    pub fn create(path: PathBuf, state: UploadState) -> Result<Self, SwishError> {
+        let state_file = Self {
+            path,
+            state: Arc::new(Mutex::new(state)),
+        };
+        state_file.save(&state_file.state.lock().unwrap())?;
+        log::info!("Upload state saved to {}", state_file.path.display());
+        Ok(state_file)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SwishError> {
+        let content = std::fs::read(path)?;
+        let state: UploadState =
+            serde_json::from_slice(&content).map_err(|error| SwishError::InvalidState {
+                path: path.display().to_string(),
+                reason: error.to_string(),
+            })?;
+
+        if let Err(error) = validate_chunk_size(state.chunk_size) {
+            return Err(SwishError::InvalidState {
+                path: path.display().to_string(),
+                reason: error.to_string(),
+            });
+        }
+
+        // The chunks already sent must still be the ones on disk
+        for file in &state.files {
+            let changed = || SwishError::InvalidState {
+                path: path.display().to_string(),
+                reason: format!("{} changed since the upload started", file.path.display()),
+            };
+            let size = std::fs::metadata(&file.path)?.len();
+            if size != file.size {
+                return Err(changed());
+            }
+            let chunks = build_chunks_array(file.size as usize, state.chunk_size);
+            for (index, checksum) in &file.chunk_checksums {
+                match chunks.get(*index) {
+                    Some(chunk) if chunk.sha256(&file.path)? == *checksum => (),
+                    _ => return Err(changed()),
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    pub fn state(&self) -> UploadState {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn is_completed(&self, file_uuid: &str, chunk_index: usize) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .files
+            .iter()
+            .any(|file| file.file_uuid == file_uuid && file.completed_chunks.contains(&chunk_index))
+    }
+
+    /// Record a chunk accepted by the server, along with its SHA-256, and write the state to disk
+    /// right away
+    pub fn complete_chunk(
+        &self,
+        file_uuid: &str,
+        chunk_index: usize,
+        checksum: String,
+    ) -> Result<(), SwishError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.files.iter_mut().find(|file| file.file_uuid == file_uuid) {
+            file.completed_chunks.insert(chunk_index);
+            file.chunk_checksums.insert(chunk_index, checksum);
+        }
+        self.save(&state)
+    }
+
+    /// The upload is over, the state is useless from now on
+    pub fn remove(&self) -> Result<(), SwishError> {
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    fn save(&self, state: &UploadState) -> Result<(), SwishError> {
+        // Write then rename so a crash never leaves a half written state
+        let tmp_path = self.path.with_extension("state.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(state)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -1,4 +1,19 @@
-use swish::swissfiles::{uploadparameters::UploadParameters, Swissfiles};
+use std::path::{Path, PathBuf};
+
+use swish::api::chunks::{build_chunks_array, MIN_CHUNK_SIZE};
+use swish::api::endpoint::Endpoint;
+use swish::api::curltransport::{CurlTransport, Timeouts};
+use swish::api::model;
+use swish::api::retry::RetryPolicy;
+use swish::api::transport::{Body, Method, Request, Sending, Sink, Transport};
+use swish::cancel::CancelToken;
+use swish::errors::SwishError;
+use swish::fakeserver::{FakeServer, Failure};
+use swish::swissfiles::{
+    conflict::OnConflict, filename::safe_path, selection::FileSelection,
+    uploadparameters::UploadParameters, Swissfiles, MIN_SEGMENT_SIZE, PART_EXTENSION,
+};
+use swish::swissfiles::progress::{Direction, NoProgress, ProgressEvent, ProgressObserver};
 
 
 const TEST_FILE_BASEPATH: &str = "tests/file_samples/";
@@ -25,34 +40,2187 @@ fn hash_file(file_path: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
-#[test]
+fn sample_file() -> PathBuf {
+    //take the first file in the file_samples folder
+    std::fs::read_dir(TEST_FILE_BASEPATH).unwrap().next().unwrap().unwrap().path()
+}
 
-fn test_single_file_upload_download(){
+/// Each test downloads in its own folder as they run in parallel
+fn download_dir(test_name: &str) -> PathBuf {
+    let path = PathBuf::from(TEST_FILE_DOWNLOADED_BASEPATH).join(test_name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
 
-    //take the first file in the file_samples folder
-    let file_path = std::fs::read_dir(TEST_FILE_BASEPATH).unwrap().next().unwrap().unwrap().path();
+/// Fill `path` with the given files, their content is derived from their name so they all differ
+fn create_folder(path: &Path, files: &[(&str, usize)]) {
+    for (name, size) in files {
+        let file_path = path.join(name);
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        let content: Vec<u8> = name.bytes().cycle().take(*size).collect();
+        std::fs::write(file_path, content).unwrap();
+    }
+}
+
+fn upload(file_path: &Path, params: &UploadParameters, endpoint: &Endpoint) -> String {
+    let local_files =
+        Swissfiles::new_localfiles(file_path.to_path_buf(), params, endpoint).unwrap();
+    local_files.upload().unwrap()
+}
+
+/// Retries without the delays meant for a real server
+fn fast_retry(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        base_delay: std::time::Duration::from_millis(1),
+        max_delay: std::time::Duration::from_millis(10),
+    }
+}
+
+fn round_trip(endpoint: &Endpoint, test_name: &str) {
+    let file_path = sample_file();
     let actual_file_name = file_path.file_name().unwrap().to_str().unwrap();
 
     let orignal_hash = hash_file(file_path.to_str().unwrap());
     let default_params = UploadParameters::default();
 
     //upload the file
-    let local_files = Swissfiles::new_localfiles(file_path.clone(), &default_params).unwrap();
-    let download_link = local_files.upload().unwrap();
+    let download_link = upload(&file_path, &default_params, endpoint);
 
     // Download the file
-    let remote_files = Swissfiles::new_remotefiles(&download_link, None).unwrap();
-    remote_files.download(None).unwrap();
+    let out_path = download_dir(test_name);
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
 
-    let downloaded_file_path = TEST_FILE_DOWNLOADED_BASEPATH.to_string() + actual_file_name;
-    let downloaded_hash = hash_file(&downloaded_file_path);
+    let downloaded_file_path = out_path.join(actual_file_name);
+    let downloaded_hash = hash_file(downloaded_file_path.to_str().unwrap());
     assert_eq!(orignal_hash, downloaded_hash);
-    std::fs::remove_file(&downloaded_file_path).unwrap();
+    std::fs::remove_dir_all(&out_path).unwrap();
+}
+
+#[test]
+#[ignore = "talks to the live SwissTransfer service"]
+fn test_single_file_upload_download_live() {
+    round_trip(&Endpoint::default(), "single_file_live");
+}
+
+#[test]
+fn test_single_file_upload_download() {
+    let server = FakeServer::start();
+    round_trip(&server.endpoint(), "single_file");
+}
+
+#[test]
+fn test_upload_declares_container() {
+    let server = FakeServer::start();
+    let params = UploadParameters {
+        message: "Hello World".to_string(),
+        number_of_download: 42,
+        duration: 7,
+        ..UploadParameters::default()
+    };
+
+    let download_link = upload(&sample_file(), &params, &server.endpoint());
+
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.message, "Hello World");
+    assert_eq!(transfer.number_of_download, 42);
+    assert_eq!(transfer.duration, 7);
+    assert_eq!(transfer.files.len(), 1);
+    assert_eq!(transfer.files[0].name, "esp32_datasheet_en.pdf");
+    assert_eq!(transfer.files[0].last_chunk, Some(transfer.files[0].chunks.len() - 1));
+}
+
+#[test]
+fn test_password_protected_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let params = UploadParameters {
+        password: "hunter2".to_string(),
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&sample_file(), &params, &endpoint);
+
+    let result = Swissfiles::new_remotefiles(&download_link, None, &endpoint);
+    assert!(matches!(result, Err(SwishError::PasswordRequired)));
+
+    let result = Swissfiles::new_remotefiles(&download_link, Some("hunter3"), &endpoint);
+    assert!(matches!(result, Err(SwishError::InvalidPassword)));
+
+    let out_path = download_dir("password");
+    let remote_files =
+        Swissfiles::new_remotefiles(&download_link, Some("hunter2"), &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    assert_eq!(
+        hash_file(sample_file().to_str().unwrap()),
+        hash_file(out_path.join("esp32_datasheet_en.pdf").to_str().unwrap())
+    );
+    std::fs::remove_dir_all(&out_path).unwrap();
+}
+
+#[test]
+fn test_wrong_password_injected() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let download_link = upload(&sample_file(), &UploadParameters::default(), &endpoint);
+
+    server.inject(Failure::WrongPassword);
+    let result = Swissfiles::new_remotefiles(&download_link, Some("whatever"), &endpoint);
+    assert!(matches!(result, Err(SwishError::InvalidPassword)));
+}
+
+#[test]
+fn test_virus_check_pending() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let download_link = upload(&sample_file(), &UploadParameters::default(), &endpoint);
+
+    server.inject(Failure::VirusCheckPending(1));
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    assert_eq!(remote_files.files.len(), 1);
+}
+
+#[test]
+fn test_download_error() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let download_link = upload(&sample_file(), &UploadParameters::default(), &endpoint);
+
+    server.inject(Failure::DownloadError);
+    let out_path = download_dir("download_error");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let result = remote_files.download(Some(&out_path));
+    assert!(matches!(result, Err(SwishError::DownloadNumberExceeded)));
+    assert!(!out_path.join("esp32_datasheet_en.pdf").exists());
+}
+
+#[test]
+fn test_download_number_exceeded() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let params = UploadParameters {
+        number_of_download: 1,
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&sample_file(), &params, &endpoint);
+
+    let out_path = download_dir("number_exceeded");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    let result = remote_files.download(Some(&out_path));
+    assert!(matches!(result, Err(SwishError::DownloadNumberExceeded)));
+}
+
+#[test]
+fn test_throttled_requests_are_retried() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+
+    server.inject(Failure::Throttle(2));
+    let download_link = upload(&sample_file(), &UploadParameters::default(), &endpoint);
+    assert!(server.transfer(&download_link).is_some());
+}
+
+#[test]
+fn test_unavailable_chunk_is_retried() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let params = UploadParameters {
+        retry: fast_retry(3),
+        ..UploadParameters::default()
+    };
+
+    server.inject(Failure::ChunkUnavailable(2));
+    let download_link = upload(&sample_file(), &params, &endpoint);
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.files[0].chunk_requests, 3);
+    assert_eq!(transfer.files[0].content(), std::fs::read(sample_file()).unwrap());
+}
+
+#[test]
+fn test_stalled_chunk_is_retried() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let params = UploadParameters {
+        retry: fast_retry(3),
+        ..UploadParameters::default()
+    };
+    let transport = std::sync::Arc::new(CurlTransport::with_timeouts(Timeouts {
+        low_speed_time: std::time::Duration::from_secs(1),
+        ..Timeouts::default()
+    }));
+
+    server.inject(Failure::ChunkStall(1));
+    let local_files =
+        Swissfiles::new_localfiles_with_transport(sample_file(), &params, &endpoint, transport)
+            .unwrap();
+    let download_link = local_files.upload().unwrap();
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.files[0].chunk_requests, 2);
+    assert_eq!(transfer.files[0].content(), std::fs::read(sample_file()).unwrap());
+}
+
+#[test]
+fn test_chunk_retries_run_out() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let params = UploadParameters {
+        retry: fast_retry(3),
+        ..UploadParameters::default()
+    };
+
+    server.inject(Failure::UploadError { after: 0 });
+    let local_files = Swissfiles::new_localfiles(sample_file(), &params, &endpoint).unwrap();
+    let result = local_files.upload();
+    match result {
+        Err(SwishError::ChunkUploadFailed { file, chunk, attempts, .. }) => {
+            assert_eq!(file, sample_file().file_name().unwrap().to_str().unwrap());
+            assert_eq!(chunk, 0);
+            assert_eq!(attempts, 3);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(server.transfers()[0].files[0].chunk_requests, 3);
+}
+
+#[test]
+fn test_unknown_link() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let link = endpoint.download_link("8b3b3b3b-3b3b-3b3b-3b3b-3b3b3b3b3b3b");
+
+    let result = Swissfiles::new_remotefiles(&link, None, &endpoint);
+    assert!(matches!(result, Err(SwishError::NotFound { .. })));
+}
+
+#[test]
+fn test_folder_upload_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("folder");
+    let upload_path = base_path.join("upload");
+    let files = [("first.txt", 1024), ("second.bin", 4096), ("third.txt", 10)];
+    create_folder(&upload_path, &files);
+
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.files.len(), files.len());
+    for file in &transfer.files {
+        assert_eq!(file.content().len() as u64, file.size);
+    }
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    for (name, _) in &files {
+        assert_eq!(
+            hash_file(upload_path.join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_recursive_folder_upload_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("recursive");
+    let upload_path = base_path.join("upload");
+    let files = [
+        ("root.txt", 100),
+        ("sub/dir/file.txt", 2048),
+        ("sub/other.bin", 512),
+        (".hidden/secret.txt", 10),
+        ("sub/.env", 10),
+    ];
+    create_folder(&upload_path, &files);
+
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let transfer = server.transfer(&download_link).unwrap();
+    let names: Vec<&str> = transfer.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["root.txt", "sub/dir/file.txt", "sub/other.bin"]);
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    for name in names {
+        assert_eq!(
+            hash_file(upload_path.join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_recursive_folder_include_hidden() {
+    let server = FakeServer::start();
+    let base_path = download_dir("include_hidden");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("visible.txt", 10), (".hidden/secret.txt", 10)]);
+
+    let params = UploadParameters {
+        include_hidden: true,
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&upload_path, &params, &server.endpoint());
+
+    let transfer = server.transfer(&download_link).unwrap();
+    let names: Vec<&str> = transfer.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, [".hidden/secret.txt", "visible.txt"]);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_recursive_folder_symlinks() {
+    let server = FakeServer::start();
+    let base_path = download_dir("symlinks");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("dir/file.txt", 10)]);
+    std::os::unix::fs::symlink("dir", upload_path.join("link")).unwrap();
+    // Points to its own parent, must not be walked forever
+    std::os::unix::fs::symlink("..", upload_path.join("dir/loop")).unwrap();
+
+    let download_link = upload(&upload_path, &UploadParameters::default(), &server.endpoint());
+    let transfer = server.transfer(&download_link).unwrap();
+    let names: Vec<&str> = transfer.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["dir/file.txt"]);
+
+    let params = UploadParameters {
+        follow_symlinks: true,
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&upload_path, &params, &server.endpoint());
+    let transfer = server.transfer(&download_link).unwrap();
+    let names: Vec<&str> = transfer.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["dir/file.txt", "link/file.txt"]);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+fn declared_names(
+    server: &FakeServer,
+    upload_path: &Path,
+    params: &UploadParameters,
+) -> Vec<String> {
+    let download_link = upload(upload_path, params, &server.endpoint());
+    let transfer = server.transfer(&download_link).unwrap();
+    transfer.files.into_iter().map(|file| file.name).collect()
+}
+
+#[test]
+fn test_folder_upload_filters() {
+    let server = FakeServer::start();
+    let base_path = download_dir("filters");
+    let upload_path = base_path.join("upload");
+    create_folder(
+        &upload_path,
+        &[
+            ("src/main.rs", 10),
+            ("src/lib.rs", 10),
+            ("target/debug/swish", 10),
+            ("node_modules/left-pad/index.js", 10),
+            ("web/node_modules/left-pad/index.js", 10),
+            ("docs/readme.md", 10),
+            ("build.log", 10),
+            (".git/HEAD", 10),
+            (".gitignore", 10),
+        ],
+    );
+
+    let params = UploadParameters {
+        exclude: vec!["target".to_string(), "node_modules".to_string(), "*.log".to_string()],
+        ..UploadParameters::default()
+    };
+    let names = declared_names(&server, &upload_path, &params);
+    assert_eq!(names, ["docs/readme.md", "src/lib.rs", "src/main.rs"]);
+
+    let params = UploadParameters {
+        include: vec!["*.rs".to_string(), "docs/*.md".to_string()],
+        exclude: vec!["lib.rs".to_string()],
+        ..UploadParameters::default()
+    };
+    let names = declared_names(&server, &upload_path, &params);
+    assert_eq!(names, ["docs/readme.md", "src/main.rs"]);
+
+    let params = UploadParameters {
+        include: vec!["*.nothing".to_string()],
+        ..UploadParameters::default()
+    };
+    let result = Swissfiles::new_localfiles(upload_path.clone(), &params, &server.endpoint());
+    assert!(matches!(result, Err(SwishError::NothingToUpload { .. })));
+
+    let params = UploadParameters {
+        exclude: vec!["[".to_string()],
+        ..UploadParameters::default()
+    };
+    let result = Swissfiles::new_localfiles(upload_path.clone(), &params, &server.endpoint());
+    assert!(matches!(result, Err(SwishError::InvalidPattern { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_folder_upload_ignore_files() {
+    let server = FakeServer::start();
+    let base_path = download_dir("ignore_files");
+    let upload_path = base_path.join("upload");
+    create_folder(
+        &upload_path,
+        &[
+            ("src/main.rs", 10),
+            ("src/generated.rs", 10),
+            ("target/debug/swish", 10),
+            ("notes.txt", 10),
+            ("keep.txt", 10),
+        ],
+    );
+    std::fs::write(upload_path.join(".gitignore"), "target/\n*.txt\n!keep.txt\n").unwrap();
+    std::fs::write(upload_path.join("src/.swishignore"), "generated.rs\n").unwrap();
+
+    let names = declared_names(&server, &upload_path, &UploadParameters::default());
+    assert_eq!(
+        names,
+        ["keep.txt", "notes.txt", "src/generated.rs", "src/main.rs", "target/debug/swish"]
+    );
+
+    let params = UploadParameters {
+        use_ignore_files: true,
+        ..UploadParameters::default()
+    };
+    let names = declared_names(&server, &upload_path, &params);
+    assert_eq!(names, ["keep.txt", "src/main.rs"]);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_resume_interrupted_upload() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("resume");
+    let upload_path = base_path.join("upload");
+    let files = [("a.txt", 100), ("b.txt", 200), ("c.txt", 300)];
+    create_folder(&upload_path, &files);
+
+    let params = UploadParameters {
+        state_dir: Some(base_path.clone()),
+        retry: fast_retry(1),
+        ..UploadParameters::default()
+    };
+    server.inject(Failure::UploadError { after: 2 });
+    let local_files = Swissfiles::new_localfiles(upload_path.clone(), &params, &endpoint).unwrap();
+    assert!(local_files.upload().is_err());
+
+    let state_path = local_files.state_file.as_ref().unwrap().path.clone();
+    assert!(state_path.exists());
+    drop(local_files);
+
+    server.clear_failures();
+    let resumed = Swissfiles::from_upload_state(&state_path).unwrap();
+    let download_link = resumed.upload().unwrap();
+    assert!(!state_path.exists());
+
+    // Chunks accepted before the interruption are not sent again
+    let transfer = server.transfer(&download_link).unwrap();
+    let requests: Vec<u64> = transfer.files.iter().map(|file| file.chunk_requests).collect();
+    assert_eq!(requests, [1, 1, 2]);
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    for (name, _) in &files {
+        assert_eq!(
+            hash_file(upload_path.join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_resume_with_modified_file() {
+    let server = FakeServer::start();
+    let base_path = download_dir("resume_modified");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("a.txt", 100), ("b.txt", 200)]);
+
+    let params = UploadParameters {
+        state_dir: Some(base_path.clone()),
+        retry: fast_retry(1),
+        ..UploadParameters::default()
+    };
+    server.inject(Failure::UploadError { after: 1 });
+    let local_files =
+        Swissfiles::new_localfiles(upload_path.clone(), &params, &server.endpoint()).unwrap();
+    assert!(local_files.upload().is_err());
+
+    std::fs::write(upload_path.join("b.txt"), "changed").unwrap();
+    let state_path = local_files.state_file.as_ref().unwrap().path.clone();
+    let result = Swissfiles::from_upload_state(&state_path);
+    assert!(matches!(result, Err(SwishError::InvalidState { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_resume_with_corrupted_state() {
+    let base_path = download_dir("resume_corrupted");
+    let state_path = base_path.join("swish-corrupted.state");
+    std::fs::create_dir_all(&base_path).unwrap();
+    std::fs::write(&state_path, "{ not json").unwrap();
+
+    let result = Swissfiles::from_upload_state(&state_path);
+    assert!(matches!(result, Err(SwishError::InvalidState { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_resume_with_same_size_change() {
+    let server = FakeServer::start();
+    let base_path = download_dir("resume_same_size");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("a.txt", 100), ("b.txt", 200)]);
+
+    let params = UploadParameters {
+        state_dir: Some(base_path.clone()),
+        retry: fast_retry(1),
+        ..UploadParameters::default()
+    };
+    server.inject(Failure::UploadError { after: 1 });
+    let local_files =
+        Swissfiles::new_localfiles(upload_path.clone(), &params, &server.endpoint()).unwrap();
+    assert!(local_files.upload().is_err());
+
+    // Same size, so only the checksum of the chunk already sent tells it changed
+    std::fs::write(upload_path.join("a.txt"), [b'x'; 100]).unwrap();
+    let state_path = local_files.state_file.as_ref().unwrap().path.clone();
+    let result = Swissfiles::from_upload_state(&state_path);
+    assert!(matches!(result, Err(SwishError::InvalidState { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Answers the first chunk with a 503 after changing the file it was read from
+struct ChangingTransport {
+    inner: CurlTransport,
+    path: PathBuf,
+    changed: std::sync::atomic::AtomicBool,
+}
+
+impl Transport for ChangingTransport {
+    fn send<'a>(
+        &'a self,
+        request: Request<'a>,
+        sink: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32> {
+        if !request.url.contains("/uploadChunk/")
+            || self.changed.swap(true, std::sync::atomic::Ordering::SeqCst)
+        {
+            return self.inner.send(request, sink, progress);
+        }
+        if let Body::Stream { reader, .. } = request.body {
+            std::io::copy(reader, &mut std::io::sink()).unwrap();
+        }
+        let size = std::fs::metadata(&self.path).unwrap().len() as usize;
+        std::fs::write(&self.path, vec![b'x'; size]).unwrap();
+        Box::pin(async { Ok(503) })
+    }
+}
+
+#[test]
+fn test_retry_with_changed_chunk() {
+    let server = FakeServer::start();
+    let base_path = download_dir("retry_changed_chunk");
+    create_folder(&base_path, &[("a.txt", 1000)]);
+    let transport = std::sync::Arc::new(ChangingTransport {
+        inner: CurlTransport::new(),
+        path: base_path.join("a.txt"),
+        changed: Default::default(),
+    });
+
+    let params = UploadParameters {
+        retry: fast_retry(3),
+        ..UploadParameters::default()
+    };
+    let local_files = Swissfiles::new_localfiles_with_transport(
+        base_path.join("a.txt"),
+        &params,
+        &server.endpoint(),
+        transport,
+    )
+    .unwrap();
+    match local_files.upload() {
+        Err(SwishError::ChunkUploadFailed { reason, .. }) => assert!(reason.contains("changed")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_upload_without_writable_state_dir() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("unwritable_state_dir");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("a.txt", 100)]);
+
+    // A file where the directory should be, so the state can not be written even as root
+    let state_dir = base_path.join("not-a-dir");
+    std::fs::write(&state_dir, "").unwrap();
+    let params = UploadParameters {
+        state_dir: Some(state_dir),
+        ..UploadParameters::default()
+    };
+    let local_files = Swissfiles::new_localfiles(upload_path.clone(), &params, &endpoint).unwrap();
+    assert!(local_files.state_file.is_none());
+
+    let download_link = local_files.upload().unwrap();
+    assert!(server.transfer(&download_link).is_some());
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_parallel_folder_upload() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("parallel_upload");
+    let upload_path = base_path.join("upload");
+    let names: Vec<String> = (0..12).map(|i| format!("file-{:02}.bin", i)).collect();
+    let files: Vec<(&str, usize)> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), 1000 * (i + 1)))
+        .collect();
+    create_folder(&upload_path, &files);
+
+    let params = UploadParameters {
+        jobs: 4,
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&upload_path, &params, &endpoint);
+
+    let transfer = server.transfer(&download_link).unwrap();
+    for (file, (name, size)) in transfer.files.iter().zip(&files) {
+        assert_eq!(file.name, *name);
+        assert_eq!(file.content().len(), *size);
+        assert_eq!(file.last_chunk, Some(0));
+    }
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    for (name, _) in &files {
+        assert_eq!(
+            hash_file(upload_path.join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_chunk_boundaries() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("chunk_boundaries");
+    std::fs::create_dir_all(&base_path).unwrap();
+    let file_path = base_path.join("data.bin");
+    let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&file_path, &content).unwrap();
+
+    let files = serde_json::json!([{ "name": "data.bin", "size": content.len() }]);
+    let payload = serde_json::json!({ "files": files.to_string() }).to_string();
+    let transport = CurlTransport::new();
+    let response = transport
+        .post(&endpoint.api("containers"), payload.into_bytes(), None)
+        .await
+        .unwrap();
+    let container: serde_json::Value = serde_json::from_slice(&response).unwrap();
+    let container_uuid = container["container"]["UUID"].as_str().unwrap();
+    let file_uuid = container["filesUUID"][0].as_str().unwrap();
+    let upload_host = container["uploadHost"].as_str().unwrap();
+
+    let chunks = build_chunks_array(content.len(), 3000);
+
+    // Out of order and over the same connection, what is sent must only depend on the chunk
+    let (last, others) = chunks.split_last().unwrap();
+    for chunk in others.iter().rev().chain([last]) {
+        let is_last = chunk.index == last.index;
+        let path = format!(
+            "uploadChunk/{}/{}/{}/{}",
+            container_uuid, file_uuid, chunk.index, is_last as u8
+        );
+        let mut reader = chunk.reader(&file_path).unwrap();
+        let body = Body::Stream {
+            reader: &mut reader,
+            size: chunk.size as u64,
+        };
+        let request = Request::post(&endpoint.host_api(upload_host, &path), body);
+        let status = transport
+            .send(request, &mut Vec::new(), &mut |_, _| true)
+            .await;
+        assert_eq!(status.unwrap(), 200);
+    }
+
+    let file = &server.transfers()[0].files[0];
+    assert_eq!(file.chunks.len(), 4);
+    for chunk in &chunks {
+        assert_eq!(file.chunks[&chunk.index], content[chunk.offset..chunk.offset + chunk.size]);
+    }
+    assert_eq!(file.content(), content);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_build_chunks_array_edge_cases() {
+    // The last chunk flag must be sent even when there is nothing to send
+    let chunks = build_chunks_array(0, 1024);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!((chunks[0].offset, chunks[0].size), (0, 0));
+
+    let chunks = build_chunks_array(3 * 1024, 1024);
+    let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.size).collect();
+    assert_eq!(sizes, [1024, 1024, 1024]);
+
+    let chunks = build_chunks_array(3 * 1024 + 1, 1024);
+    let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.size).collect();
+    assert_eq!(sizes, [1024, 1024, 1024, 1]);
+    assert_eq!(chunks[3].offset, 3 * 1024);
+}
+
+#[test]
+fn test_empty_file_upload_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("empty_file");
+    create_folder(&base_path, &[("empty.txt", 0)]);
+
+    let params = UploadParameters::default();
+    let download_link = upload(&base_path.join("empty.txt"), &params, &endpoint);
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.files[0].last_chunk, Some(0));
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    assert_eq!(std::fs::metadata(out_path.join("empty.txt")).unwrap().len(), 0);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_custom_chunk_size() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("custom_chunk_size");
+    let files = [("exact.bin", 2 * MIN_CHUNK_SIZE), ("longer.bin", 2 * MIN_CHUNK_SIZE + 10)];
+    create_folder(&base_path, &files);
+
+    let params = UploadParameters {
+        chunk_size: MIN_CHUNK_SIZE,
+        jobs: 2,
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&base_path, &params, &endpoint);
+
+    let transfer = server.transfer(&download_link).unwrap();
+    let chunks: Vec<usize> = transfer.files.iter().map(|file| file.chunks.len()).collect();
+    assert_eq!(chunks, [2, 3]);
+    for (file, (name, _)) in transfer.files.iter().zip(&files) {
+        assert_eq!(file.content(), std::fs::read(base_path.join(name)).unwrap());
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_invalid_chunk_size() {
+    let server = FakeServer::start();
+    let params = UploadParameters {
+        chunk_size: 1000,
+        ..UploadParameters::default()
+    };
+
+    let result = Swissfiles::new_localfiles(sample_file(), &params, &server.endpoint());
+    assert!(matches!(result, Err(SwishError::InvalidChunkSize { size: 1000, .. })));
+    assert!(server.transfers().is_empty());
+}
+
+#[test]
+fn test_interrupted_download_continued() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let file_path = sample_file();
+    let name = file_path.file_name().unwrap().to_str().unwrap();
+    let size = std::fs::metadata(&file_path).unwrap().len();
+    let download_link = upload(&file_path, &UploadParameters::default(), &endpoint);
+    let out_path = download_dir("download_continued");
+
+    server.inject(Failure::DownloadCut { after: 1000 });
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let result = remote_files.download(Some(&out_path));
+    assert!(matches!(result, Err(SwishError::IncompleteDownload { received: 1000, .. })));
+    // Nothing under the final name until the file is complete
+    let part_path = out_path.join(format!("{}{}", name, PART_EXTENSION));
+    assert!(!out_path.join(name).exists());
+    assert_eq!(std::fs::metadata(&part_path).unwrap().len(), 1000);
+
+    remote_files.continue_downloads = true;
+    remote_files.download(Some(&out_path)).unwrap();
+    assert!(!part_path.exists());
+    assert_eq!(
+        hash_file(file_path.to_str().unwrap()),
+        hash_file(out_path.join(name).to_str().unwrap())
+    );
+    // Only what was missing has been sent again
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(transfer.files[0].bytes_served, size);
+
+    // A complete file is left alone
+    remote_files.download(Some(&out_path)).unwrap();
+    assert_eq!(server.transfer(&download_link).unwrap().files[0].bytes_served, size);
+    std::fs::remove_dir_all(&out_path).unwrap();
+}
+
+/// Cancels `0` as soon as some bytes went through
+struct CancelOnBytes(CancelToken);
+
+impl ProgressObserver for CancelOnBytes {
+    fn on_event(&self, event: ProgressEvent) {
+        if let ProgressEvent::BytesTransferred { .. } = event {
+            self.0.cancel();
+        }
+    }
+}
+
+#[test]
+fn test_cancelled_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let file_path = sample_file();
+    let name = file_path.file_name().unwrap().to_str().unwrap();
+    let download_link = upload(&file_path, &UploadParameters::default(), &endpoint);
+    let out_path = download_dir("download_cancelled");
+
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.progress = std::sync::Arc::new(CancelOnBytes(remote_files.cancel.clone()));
+    let result = remote_files.download(Some(&out_path));
+    assert!(matches!(result, Err(SwishError::Interrupted)));
+    // Neither the file nor its part file is left behind
+    assert!(!out_path.join(name).exists());
+    assert!(!out_path.join(format!("{}{}", name, PART_EXTENSION)).exists());
+    std::fs::remove_dir_all(&out_path).unwrap();
+}
+
+#[test]
+fn test_continue_when_range_is_ignored() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let file_path = sample_file();
+    let name = file_path.file_name().unwrap().to_str().unwrap();
+    let size = std::fs::metadata(&file_path).unwrap().len();
+    let download_link = upload(&file_path, &UploadParameters::default(), &endpoint);
+    let out_path = download_dir("download_range_ignored");
+    std::fs::create_dir_all(&out_path).unwrap();
+    let part_path = out_path.join(format!("{}{}", name, PART_EXTENSION));
+    std::fs::write(&part_path, vec![0; 1000]).unwrap();
+
+    server.inject(Failure::IgnoreRange);
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.continue_downloads = true;
+    remote_files.download(Some(&out_path)).unwrap();
+
+    // The partial file is overwritten by the whole file sent by the server
+    assert!(!part_path.exists());
+    assert_eq!(
+        hash_file(file_path.to_str().unwrap()),
+        hash_file(out_path.join(name).to_str().unwrap())
+    );
+    assert_eq!(server.transfer(&download_link).unwrap().files[0].bytes_served, size);
+    std::fs::remove_dir_all(&out_path).unwrap();
+}
+
+#[test]
+fn test_parallel_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("parallel_download");
+    let upload_path = base_path.join("upload");
+    let files = [("a.txt", 100), ("b/c.txt", 2000), ("b/d.bin", 0), ("e.bin", 30000), ("f", 5)];
+    create_folder(&upload_path, &files);
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let out_path = base_path.join("download");
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.jobs = 3;
+    remote_files.download(Some(&out_path)).unwrap();
+    for (name, _) in &files {
+        assert_eq!(
+            hash_file(upload_path.join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_download_failures_reported_together() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("download_failures");
+    let upload_path = base_path.join("upload");
+    let files = [("a.txt", 100), ("b.txt", 200), ("c.txt", 300), ("d.txt", 400)];
+    create_folder(&upload_path, &files);
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    server.inject(Failure::MissingFile("d.txt".to_string()));
+    server.inject(Failure::MissingFile("b.txt".to_string()));
+    let out_path = base_path.join("download");
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.jobs = 2;
+    match remote_files.download(Some(&out_path)) {
+        Err(SwishError::DownloadsFailed { errors }) => {
+            let names: Vec<&str> = errors.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["b.txt", "d.txt"]);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The other files are downloaded anyway
+    assert!(out_path.join("a.txt").exists());
+    assert!(out_path.join("c.txt").exists());
+    assert!(!out_path.join("b.txt").exists());
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_segmented_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("segmented_download");
+    let size = 3 * MIN_SEGMENT_SIZE + 123;
+    create_folder(&base_path, &[("big.bin", size)]);
+    let params = UploadParameters::default();
+    let download_link = upload(&base_path.join("big.bin"), &params, &endpoint);
+
+    let out_path = base_path.join("download");
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.segments = 8;
+    remote_files.download(Some(&out_path)).unwrap();
+
+    // Ranges are never smaller than MIN_SEGMENT_SIZE, so 4 of them rather than 8
+    let file = &server.transfer(&download_link).unwrap().files[0];
+    assert_eq!(file.download_counter, 4);
+    assert_eq!(file.bytes_served, size as u64);
+    assert_eq!(
+        hash_file(base_path.join("big.bin").to_str().unwrap()),
+        hash_file(out_path.join("big.bin").to_str().unwrap())
+    );
+    assert!(!out_path.join(format!("big.bin{}", PART_EXTENSION)).exists());
+    std::fs::remove_dir_all(&base_path).unwrap();
 }
 
+#[test]
+fn test_segmented_download_without_ranges() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("segmented_download_without_ranges");
+    create_folder(&base_path, &[("big.bin", 2 * MIN_SEGMENT_SIZE)]);
+    let params = UploadParameters::default();
+    let download_link = upload(&base_path.join("big.bin"), &params, &endpoint);
+
+    server.inject(Failure::IgnoreRange);
+    let out_path = base_path.join("download");
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.segments = 2;
+    remote_files.download(Some(&out_path)).unwrap();
+
+    // The file is downloaded again in one go
+    assert_eq!(
+        hash_file(base_path.join("big.bin").to_str().unwrap()),
+        hash_file(out_path.join("big.bin").to_str().unwrap())
+    );
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
 
+/// Upload `a.txt` and `b.txt`, put `existing` in the output directory then download with `on_conflict`
+fn download_over(
+    test_name: &str,
+    existing: &[(&str, &str)],
+    on_conflict: OnConflict,
+) -> (PathBuf, Result<(), SwishError>) {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir(test_name);
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("a.txt", 100), ("b.txt", 200)]);
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
 
+    let out_path = base_path.join("download");
+    std::fs::create_dir_all(&out_path).unwrap();
+    for (name, content) in existing {
+        std::fs::write(out_path.join(name), content).unwrap();
+    }
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.on_conflict = on_conflict;
+    let result = remote_files.download(Some(&out_path)).map(|_| ());
+    (base_path, result)
+}
 
+fn read_to_string(path: PathBuf) -> String {
+    String::from_utf8_lossy(&std::fs::read(path).unwrap()).to_string()
+}
 
+#[test]
+fn test_on_conflict_overwrite() {
+    let (base_path, result) =
+        download_over("conflict_overwrite", &[("a.txt", "old")], OnConflict::Overwrite);
+    result.unwrap();
+    let out_path = base_path.join("download");
+    assert_eq!(read_to_string(out_path.join("a.txt")).len(), 100);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_on_conflict_skip() {
+    let (base_path, result) = download_over("conflict_skip", &[("a.txt", "old")], OnConflict::Skip);
+    result.unwrap();
+    let out_path = base_path.join("download");
+    assert_eq!(read_to_string(out_path.join("a.txt")), "old");
+    assert_eq!(read_to_string(out_path.join("b.txt")).len(), 200);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_on_conflict_skip_same_size() {
+    let same_size = "x".repeat(100);
+    let existing = [("a.txt", same_size.as_str()), ("b.txt", "old")];
+    let (base_path, result) =
+        download_over("conflict_skip_same_size", &existing, OnConflict::SkipSameSize);
+    result.unwrap();
+    let out_path = base_path.join("download");
+    assert_eq!(read_to_string(out_path.join("a.txt")), same_size);
+    assert_eq!(read_to_string(out_path.join("b.txt")).len(), 200);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
 
+#[test]
+fn test_on_conflict_rename() {
+    let existing = [("a.txt", "old"), ("a (1).txt", "older")];
+    let (base_path, result) = download_over("conflict_rename", &existing, OnConflict::Rename);
+    result.unwrap();
+    let out_path = base_path.join("download");
+    assert_eq!(read_to_string(out_path.join("a.txt")), "old");
+    assert_eq!(read_to_string(out_path.join("a (1).txt")), "older");
+    assert_eq!(read_to_string(out_path.join("a (2).txt")).len(), 100);
+    assert_eq!(read_to_string(out_path.join("b.txt")).len(), 200);
+    assert!(!out_path.join("b (1).txt").exists());
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_on_conflict_fail() {
+    let (base_path, result) =
+        download_over("conflict_fail", &[("a.txt", "old")], OnConflict::Fail);
+    assert!(matches!(result, Err(SwishError::FileExists { .. })));
+    let out_path = base_path.join("download");
+    assert_eq!(read_to_string(out_path.join("a.txt")), "old");
+    // The other files are still downloaded
+    assert_eq!(read_to_string(out_path.join("b.txt")).len(), 200);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_safe_path() {
+    assert_eq!(safe_path("file.txt").unwrap(), PathBuf::from("file.txt"));
+    assert_eq!(
+        safe_path("folder/sub/file.txt").unwrap(),
+        Path::new("folder").join("sub").join("file.txt")
+    );
+    assert_eq!(safe_path("..file.txt").unwrap(), PathBuf::from("..file.txt"));
+    assert_eq!(safe_path("console.log").unwrap(), PathBuf::from("console.log"));
+    #[cfg(not(windows))]
+    for name in ["notes 10:30.txt", "con.txt", "aux.c", "a.txt:ads", "trailing."] {
+        assert_eq!(safe_path(name).unwrap(), PathBuf::from(name));
+    }
+    #[cfg(windows)]
+    for (name, path) in [
+        ("notes 10:30.txt", "notes 10_30.txt"),
+        ("con.txt", "_con.txt"),
+        ("folder/Lpt1.tar.gz", "folder\\_Lpt1.tar.gz"),
+        ("sub/C:evil", "sub\\C_evil"),
+        ("trailing.", "trailing._"),
+    ] {
+        assert_eq!(safe_path(name).unwrap(), PathBuf::from(path));
+    }
+
+    let hostile = [
+        "",
+        "../../.bashrc",
+        "folder/../../escape.txt",
+        "..",
+        "/etc/passwd",
+        "\\\\server\\share\\file.txt",
+        "..\\..\\escape.txt",
+        "C:\\Windows\\file.txt",
+        "c:file.txt",
+        "folder//file.txt",
+        "./file.txt",
+        "folder/",
+        "file\0.txt",
+        "line\nbreak.txt",
+        "bell\u{7}.txt",
+    ];
+    for name in hostile {
+        assert!(
+            matches!(safe_path(name), Err(SwishError::UnsafeFileName { .. })),
+            "{:?} was accepted",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_hostile_file_names() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("hostile_file_names");
+    let upload_path = base_path.join("upload");
+    let names = ["a.txt", "b.txt", "c.txt", "d.txt", "safe.txt"];
+    let files: Vec<(&str, usize)> = names.iter().map(|name| (*name, 100)).collect();
+    create_folder(&upload_path, &files);
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let out_path = base_path.join("download").join("inside");
+    let escape_path = std::fs::canonicalize(&base_path).unwrap().join("escape.txt");
+    server.rename_file(&download_link, "a.txt", "../../escape.txt");
+    server.rename_file(&download_link, "b.txt", escape_path.to_str().unwrap());
+    server.rename_file(&download_link, "c.txt", "folder/../../../escape.txt");
+    server.rename_file(&download_link, "d.txt", "escape\0.txt");
+
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let result = remote_files.download(Some(&out_path));
+
+    match result {
+        Err(SwishError::DownloadsFailed { errors }) => {
+            assert_eq!(errors.len(), 4);
+            for (_, error) in errors {
+                assert!(matches!(error, SwishError::UnsafeFileName { .. }), "{}", error);
+            }
+        }
+        other => panic!("Expected DownloadsFailed, got {:?}", other),
+    }
+    assert!(!escape_path.exists());
+    assert!(!base_path.join("download").join("escape.txt").exists());
+    assert_eq!(std::fs::read(out_path.join("safe.txt")).unwrap().len(), 100);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_colon_file_names() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("colon_file_names");
+    let upload_path = base_path.join("upload");
+    let names = ["notes 10:30.txt", "con.txt", "aux.c"];
+    let files: Vec<(&str, usize)> = names.iter().map(|name| (*name, 100)).collect();
+    create_folder(&upload_path, &files);
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+
+    for name in names {
+        assert_eq!(
+            std::fs::read(out_path.join(name)).unwrap(),
+            std::fs::read(upload_path.join(name)).unwrap()
+        );
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Upload a small tree and download what `selection` picks, returns the names of the downloaded files
+fn download_selection(
+    test_name: &str,
+    selection: impl Fn(&Swissfiles) -> FileSelection,
+) -> Vec<String> {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir(test_name);
+    let upload_path = base_path.join("upload");
+    create_folder(
+        &upload_path,
+        &[("a.txt", 10), ("b.pdf", 20), ("docs/c.pdf", 30), ("docs/d.md", 40)],
+    );
+    let download_link = upload(&upload_path, &UploadParameters::default(), &endpoint);
+
+    let out_path = base_path.join("download");
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let selection = selection(&remote_files);
+    remote_files.select(&selection).unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+
+    let mut names: Vec<String> = ["a.txt", "b.pdf", "docs/c.pdf", "docs/d.md"]
+        .into_iter()
+        .filter(|name| out_path.join(name).exists())
+        .map(String::from)
+        .collect();
+    names.sort();
+    std::fs::remove_dir_all(&base_path).unwrap();
+    names
+}
+
+#[test]
+fn test_select_only() {
+    let names = download_selection("select_only", |_| FileSelection {
+        only: vec!["*.pdf".to_string()],
+        ..FileSelection::default()
+    });
+    assert_eq!(names, ["b.pdf", "docs/c.pdf"]);
+}
+
+#[test]
+fn test_select_exclude() {
+    let names = download_selection("select_exclude", |_| FileSelection {
+        exclude: vec!["docs".to_string(), "a.txt".to_string()],
+        ..FileSelection::default()
+    });
+    assert_eq!(names, ["b.pdf"]);
+}
+
+#[test]
+fn test_select_only_and_exclude() {
+    let names = download_selection("select_only_and_exclude", |_| FileSelection {
+        only: vec!["docs/*".to_string()],
+        exclude: vec!["*.md".to_string()],
+        ..FileSelection::default()
+    });
+    assert_eq!(names, ["docs/c.pdf"]);
+}
+
+#[test]
+fn test_select_file_uuid() {
+    let names = download_selection("select_file_uuid", |remote_files| {
+        let uuid = remote_files
+            .remote_files()
+            .find(|file| file.name == "docs/d.md")
+            .unwrap()
+            .uuid
+            .clone();
+        FileSelection {
+            only: vec!["a.txt".to_string()],
+            file_uuids: vec![uuid, "00000000-0000-4000-8000-000000000000".to_string()],
+            ..FileSelection::default()
+        }
+    });
+    assert_eq!(names, ["a.txt", "docs/d.md"]);
+}
+
+#[test]
+fn test_select_nothing() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("select_nothing");
+    create_folder(&base_path, &[("a.txt", 10)]);
+    let download_link = upload(&base_path, &UploadParameters::default(), &endpoint);
+
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let selection = FileSelection {
+        only: vec!["*.pdf".to_string()],
+        ..FileSelection::default()
+    };
+    assert!(matches!(remote_files.select(&selection), Err(SwishError::NothingToDownload)));
+
+    let selection = FileSelection {
+        only: vec!["[".to_string()],
+        ..FileSelection::default()
+    };
+    assert!(matches!(remote_files.select(&selection), Err(SwishError::InvalidPattern { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_transfer_info() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("transfer_info");
+    create_folder(&base_path, &[("a.txt", 10), ("docs/b.pdf", 2048)]);
+    let download_link = upload(&base_path, &UploadParameters::default(), &endpoint);
+
+    let remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let info = remote_files.info();
+    let transfer = server.transfer(&download_link).unwrap();
+    assert_eq!(info.container_uuid, transfer.container_uuid);
+    assert_eq!(info.total_size, 2058);
+    assert_eq!(info.files.len(), 2);
+    let pdf = info.files.iter().find(|file| file.name == "docs/b.pdf").unwrap();
+    assert_eq!(pdf.size, 2048);
+    assert_eq!(pdf.mime_type, "application/pdf");
+    assert_eq!(pdf.expired_date, "2024-01-31 00:00:00");
+    assert_eq!(pdf.download_counter, 0);
+    assert_eq!(pdf.virus_scan, "CLEAN");
+
+    let table = info.to_string();
+    assert!(table.contains("2 file(s), 2.01 KiB, expires 2024-01-31 00:00:00"));
+    assert!(table.contains("docs/b.pdf"));
+    assert!(table.contains(&pdf.uuid));
+
+    let json = serde_json::to_value(&info).unwrap();
+    assert_eq!(json["files"].as_array().unwrap().len(), 2);
+    assert_eq!(json["total_size"], 2058);
+
+    // Nothing has been downloaded
+    let transfer = server.transfer(&download_link).unwrap();
+    assert!(transfer.files.iter().all(|file| file.bytes_served == 0));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Run the swish binary against `endpoint`, returns its exit code, stdout and stderr
+fn run_swish(endpoint: &Endpoint, args: &[&str]) -> (i32, String, String) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_swish"))
+        .args(args)
+        .env("SWISH_API_URL", &endpoint.base_url)
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_json_output() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("json_output");
+    let upload_path = base_path.join("upload");
+    create_folder(&upload_path, &[("a.txt", 10), ("docs/b.pdf", 2048)]);
+
+    let (code, stdout, _) = run_swish(
+        &endpoint,
+        &["--json", "--no-state", "-d", "7", "-m", "Hello", upload_path.to_str().unwrap()],
+    );
+    assert_eq!(code, 0);
+    let upload: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let transfer = server.transfers().remove(0);
+    assert_eq!(upload["container_uuid"], transfer.container_uuid);
+    assert_eq!(upload["expired_date"], "2024-01-08 00:00:00");
+    assert_eq!(upload["parameters"]["duration"], 7);
+    assert_eq!(upload["parameters"]["message"], "Hello");
+    assert_eq!(upload["parameters"]["password_protected"], false);
+    let files = upload["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    for file in files {
+        let fake_file = transfer.files.iter().find(|fake| fake.uuid == file["uuid"]).unwrap();
+        assert_eq!(file["name"], fake_file.name);
+        assert_eq!(file["size"], fake_file.size);
+    }
+
+    let download_link = upload["download_link"].as_str().unwrap();
+    let out_path = base_path.join("download");
+    let (code, stdout, _) =
+        run_swish(&endpoint, &["--json", "-o", out_path.to_str().unwrap(), download_link]);
+    assert_eq!(code, 0);
+    let download: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(download["container_uuid"], transfer.container_uuid);
+    let files = download["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    for file in files {
+        let name = file["name"].as_str().unwrap();
+        let path = file["path"].as_str().unwrap();
+        assert_eq!(Path::new(path), out_path.join(name));
+        assert_eq!(file["sha256"], hash_file(path));
+        assert_eq!(file["sha256"], hash_file(upload_path.join(name).to_str().unwrap()));
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_json_errors() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("json_errors");
+    create_folder(&base_path, &[("a.txt", 10)]);
+    let params = UploadParameters {
+        password: "secret".to_string(),
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&base_path, &params, &endpoint);
+
+    let (code, stdout, stderr) = run_swish(&endpoint, &["info", "--json", &download_link]);
+    assert_eq!(code, 10);
+    assert!(stdout.is_empty());
+    let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+    assert_eq!(error["error"]["code"], "password_required");
+    assert_eq!(error["error"]["exit_code"], 10);
+    assert!(error["error"]["message"].as_str().unwrap().contains("password"));
+
+    server.inject(Failure::DownloadError);
+    let out_path = base_path.join("download");
+    let args = ["--json", "-p", "secret", "-o", out_path.to_str().unwrap(), &download_link];
+    let (code, _, stderr) = run_swish(&endpoint, &args);
+    assert_eq!(code, 13);
+    let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+    assert_eq!(error["error"]["code"], "download_number_exceeded");
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_exit_codes() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("exit_codes");
+    create_folder(&base_path, &[("a.txt", 10)]);
+    let params = UploadParameters {
+        password: "secret".to_string(),
+        ..UploadParameters::default()
+    };
+    let download_link = upload(&base_path, &params, &endpoint);
+
+    let (code, _, stderr) = run_swish(&endpoint, &[&download_link]);
+    assert_eq!(code, 10);
+    assert_eq!(stderr.trim(), format!("Error: {}", SwishError::PasswordRequired));
+
+    let (code, _, _) = run_swish(&endpoint, &["-p", "wrong", &download_link]);
+    assert_eq!(code, 11);
+
+    let (code, _, _) = run_swish(&endpoint, &["-p", "secret", "--only", "*.pdf", &download_link]);
+    assert_eq!(code, 7);
+
+    let (code, _, _) = run_swish(&endpoint, &["not/a/file/nor/a/link"]);
+    assert_eq!(code, 3);
+
+    let (code, _, _) = run_swish(&endpoint, &["--jobs", "0", &download_link]);
+    assert_eq!(code, 2);
+
+    // Each kind of error has its own code
+    let errors = [
+        SwishError::InvalidUrl { url: String::new() },
+        SwishError::InvalidJson { json: String::new() },
+        SwishError::InvalidResponse { response: String::new() },
+        SwishError::FileError { error: std::io::Error::other("") },
+        SwishError::NotFound { url: String::new() },
+        SwishError::PasswordRequired,
+        SwishError::InvalidPassword,
+        SwishError::DownloadNumberExceeded,
+        SwishError::InvalidPattern { pattern: String::new(), error: String::new() },
+        SwishError::NothingToUpload { path: String::new() },
+        SwishError::NothingToDownload,
+        SwishError::InvalidState { path: String::new(), reason: String::new() },
+        SwishError::InvalidChunkSize { size: 0, min: 0, max: 0 },
+        SwishError::Interrupted,
+        SwishError::FileExists { path: String::new() },
+        SwishError::DownloadsFailed { errors: Vec::new() },
+        SwishError::IncompleteDownload { name: String::new(), received: 0, expected: 0 },
+        SwishError::ChunkUploadFailed {
+            file: String::new(),
+            chunk: 0,
+            attempts: 0,
+            reason: String::new(),
+        },
+        SwishError::UnsafeFileName { name: String::new(), reason: "" },
+        SwishError::MissingField { path: String::new() },
+        SwishError::UnexpectedResponse { status: 0, body: String::new() },
+    ];
+    let mut codes: Vec<u8> = errors.iter().map(SwishError::exit_code).collect();
+    assert!(codes.iter().all(|code| ![0, 1, 2].contains(code)));
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), errors.len());
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Answer the next request to `path` with `body` instead of what the server would say
+fn raw_response(server: &FakeServer, path: &str, status: u16, body: &str) {
+    server.inject(Failure::RawResponse {
+        path: path.to_string(),
+        status,
+        body: body.to_string(),
+    });
+}
+
+#[test]
+fn test_malformed_links_response() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("malformed_links_response");
+    create_folder(&base_path, &[("a.txt", 10)]);
+    let download_link = upload(&base_path, &UploadParameters::default(), &endpoint);
+    let open = || Swissfiles::new_remotefiles(&download_link, None, &endpoint);
+
+    raw_response(&server, "/api/links/", 200, r#"{"data": {"linkUUID": "abc", "container": {"#);
+    assert!(matches!(open(), Err(SwishError::InvalidJson { .. })));
+
+    let page = "<html><body><h1>Service Unavailable</h1></body></html>";
+    raw_response(&server, "/api/links/", 503, page);
+    match open() {
+        Err(SwishError::UnexpectedResponse { status, body }) => {
+            assert_eq!(status, 503);
+            assert_eq!(body, page);
+        }
+        other => panic!("Expected UnexpectedResponse, got {:?}", other.err()),
+    }
+
+    raw_response(&server, "/api/links/", 200, r#"{"data": {"message": "Something new"}}"#);
+    match open() {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/data/downloadHost"),
+        other => panic!("Expected MissingField, got {:?}", other.err()),
+    }
+
+    let without_uuid =
+        r#"{"data": {"linkUUID": "l", "downloadHost": "h", "container": {"files": []}}}"#;
+    raw_response(&server, "/api/links/", 200, without_uuid);
+    match open() {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/data/container/UUID"),
+        other => panic!("Expected MissingField, got {:?}", other.err()),
+    }
+
+    let container =
+        r#"{"data": {"linkUUID": "l", "downloadHost": "h", "container": {"UUID": "c", "files": "#;
+    let files = r#"[{"UUID": "f", "fileName": "a.txt", "fileSizeInBytes": "10"}]}}}"#;
+    raw_response(&server, "/api/links/", 200, &format!("{}{}", container, files));
+    match open() {
+        Err(SwishError::MissingField { path }) => {
+            assert_eq!(path, "/data/container/files/0/fileSizeInBytes")
+        }
+        other => panic!("Expected MissingField, got {:?}", other.err()),
+    }
+
+    // Only what is needed to download a file is required
+    let files = r#"[{"UUID": "f", "fileName": "a.txt", "fileSizeInBytes": 10}]}}}"#;
+    raw_response(&server, "/api/links/", 200, &format!("{}{}", container, files));
+    let remote_files = open().unwrap();
+    let file = remote_files.remote_files().next().unwrap();
+    assert_eq!((file.name.as_str(), file.size), ("a.txt", 10));
+    assert_eq!(file.deleted_date, None);
+    assert_eq!(file.mime_type, "");
+
+    // The server is fine again
+    assert_eq!(open().unwrap().remote_files().count(), 1);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[test]
+fn test_malformed_upload_responses() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("malformed_upload_responses");
+    create_folder(&base_path, &[("a.txt", 10)]);
+    let params = UploadParameters::default();
+    let open = || Swissfiles::new_localfiles(base_path.clone(), &params, &endpoint);
 
+    raw_response(&server, "/api/containers", 200, "<html>Maintenance</html>");
+    assert!(matches!(open(), Err(SwishError::InvalidJson { .. })));
+
+    raw_response(&server, "/api/containers", 200, r#"{"container": {"UUID": "c"}}"#);
+    match open() {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/filesUUID"),
+        other => panic!("Expected MissingField, got {:?}", other.err()),
+    }
+
+    let without_files = r#"{"container": {"UUID": "c"}, "filesUUID": [], "uploadHost": "h"}"#;
+    raw_response(&server, "/api/containers", 200, without_files);
+    match open() {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/filesUUID/0"),
+        other => panic!("Expected MissingField, got {:?}", other.err()),
+    }
+
+    // Error statuses are retried a few times before giving up
+    server.inject(Failure::Throttle(10));
+    match open() {
+        Err(SwishError::UnexpectedResponse { status, .. }) => assert_eq!(status, 429),
+        other => panic!("Expected UnexpectedResponse, got {:?}", other.err()),
+    }
+    server.clear_failures();
+
+    let local_files = open().unwrap();
+    raw_response(&server, "/api/uploadComplete", 200, "[]");
+    match local_files.upload() {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/0/linkUUID"),
+        other => panic!("Expected MissingField, got {:?}", other),
+    }
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+// Written by hand, not captured from the service, see tests/fixtures/README.md
+const TEST_FIXTURES_BASEPATH: &str = "tests/fixtures/";
+
+fn read_fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}{}", TEST_FIXTURES_BASEPATH, name)).unwrap()
+}
+
+/// Every value the model wrote back is the one found at the same place in the fixture
+fn assert_written_back(written: &serde_json::Value, fixture: &serde_json::Value, at: &str) {
+    use serde_json::Value;
+
+    match (written, fixture) {
+        (Value::Object(written), Value::Object(fixture)) => {
+            for (key, value) in written {
+                let path = format!("{}/{}", at, key);
+                match fixture.get(key) {
+                    Some(expected) => assert_written_back(value, expected, &path),
+                    None => assert!(value.is_null(), "{} is not in the fixture", path),
+                }
+            }
+        }
+        (Value::Array(written), Value::Array(fixture)) => {
+            assert_eq!(written.len(), fixture.len(), "{} has another length", at);
+            for (index, (value, expected)) in written.iter().zip(fixture).enumerate() {
+                assert_written_back(value, expected, &format!("{}/{}", at, index));
+            }
+        }
+        _ => assert_eq!(written, fixture, "{} differs", at),
+    }
+}
+
+/// Parses a fixture, writes it back and parses it again
+fn model_round_trip<T>(name: &str) -> T
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    let body = read_fixture(name);
+    let parsed: T = model::parse(&body).unwrap();
+
+    let fixture: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let written = serde_json::to_value(&parsed).unwrap();
+    assert_written_back(&written, &fixture, "");
+
+    let reparsed: T = serde_json::from_value(written).unwrap();
+    assert_eq!(parsed, reparsed);
+    parsed
+}
+
+#[test]
+fn test_model_requests() {
+    // Requests are written entirely by swish, nothing may be lost on the way
+    for name in [
+        "containers_request.json",
+        "upload_complete_request.json",
+        "download_token_request.json",
+    ] {
+        let fixture: serde_json::Value = serde_json::from_slice(&read_fixture(name)).unwrap();
+        let written = match name {
+            "containers_request.json" => {
+                serde_json::to_value(model_round_trip::<model::ContainerRequest>(name)).unwrap()
+            }
+            "upload_complete_request.json" => {
+                serde_json::to_value(model_round_trip::<model::UploadCompleteRequest>(name)).unwrap()
+            }
+            _ => serde_json::to_value(model_round_trip::<model::DownloadTokenRequest>(name)).unwrap(),
+        };
+        assert_eq!(written, fixture, "{}", name);
+    }
+
+    let request: model::ContainerRequest = model_round_trip("containers_request.json");
+    let files: Vec<model::ContainerFile> = serde_json::from_str(&request.files).unwrap();
+    assert_eq!(
+        files,
+        vec![
+            model::ContainerFile {
+                name: String::from("pictures/beach.jpg"),
+                size: 2097152,
+            },
+            model::ContainerFile {
+                name: String::from("pictures/notes.txt"),
+                size: 1048576,
+            },
+        ]
+    );
+    assert_eq!(
+        request.size_of_upload,
+        files.iter().map(|file| file.size).sum::<u64>()
+    );
+}
+
+#[test]
+fn test_model_responses() {
+    let container: model::ContainerResponse = model_round_trip("containers_response.json");
+    assert_eq!(container.container.uuid, "4c0a1f0e-9b8e-4d5f-8a47-2f6f3c1d9e21");
+    assert_eq!(container.container.duration, Some(30));
+    assert_eq!(
+        container.container.expired_date.as_deref(),
+        Some("2024-04-19 10:12:55")
+    );
+    assert_eq!(container.files_uuid.len(), 2);
+    assert_eq!(container.upload_host, "upload-2b7c1d9e.swisstransfer.com");
+
+    let links: model::UploadCompleteResponse = model_round_trip("upload_complete_response.json");
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].link_uuid, "188be047-5b8c-48bf-9c4a-e70076c0e53c");
+
+    let link: model::LinksResponse = model_round_trip("links_response.json");
+    assert_eq!(link.data.link_uuid, links[0].link_uuid);
+    assert_eq!(link.data.download_host, "dl-4a21b4c5.swisstransfer.com");
+    assert_eq!(link.data.container.uuid, container.container.uuid);
+    let names: Vec<&str> = link
+        .data
+        .container
+        .files
+        .iter()
+        .map(|file| file.file_name.as_str())
+        .collect();
+    assert_eq!(names, ["pictures/beach.jpg", "pictures/notes.txt"]);
+    let beach = &link.data.container.files[0];
+    assert_eq!(beach.uuid, container.files_uuid[0]);
+    assert_eq!(beach.file_size_in_bytes, 2097152);
+    assert_eq!(beach.download_counter, Some(1));
+    assert_eq!(beach.deleted_date, None);
+    assert_eq!(beach.e_virus.as_deref(), Some("CLEAN"));
+    assert_eq!(beach.mime_type.as_deref(), Some("image/jpeg"));
+
+    // A transfer asking for a password carries a message instead of the files
+    let status: model::LinksStatus = model_round_trip("links_password_required.json");
+    assert_eq!(status.data.message.as_deref(), Some("Transfer need a password"));
+    let body = read_fixture("links_password_required.json");
+    assert!(model::parse::<model::LinksResponse>(&body).is_err());
+    let status: model::LinksStatus = model_round_trip("links_response.json");
+    assert_eq!(status.data.message, None);
+
+    let token: model::DownloadToken = model_round_trip("download_token_response.json");
+    assert_eq!(token.0, "8a1f4e2b-7c3d-4e5f-9a6b-0c1d2e3f4a5b");
+}
+
+#[test]
+fn test_model_optional_fields() {
+    // Only what swish needs to download a file is mandatory
+    let file: model::FileEntry = model::parse(
+        br#"{"UUID": "1f2d3c4b", "fileName": "a.txt", "fileSizeInBytes": 3}"#,
+    )
+    .unwrap();
+    assert_eq!(file.file_name, "a.txt");
+    assert_eq!(file.created_date, None);
+    assert_eq!(file.download_counter, None);
+    assert_eq!(file.e_virus, None);
+
+    let container: model::ContainerResponse = model::parse(
+        br#"{"container": {"UUID": "4c0a1f0e"}, "filesUUID": [], "uploadHost": "localhost"}"#,
+    )
+    .unwrap();
+    assert_eq!(container.container.duration, None);
+    assert_eq!(container.container.expired_date, None);
+
+    match model::parse::<model::FileEntry>(br#"{"UUID": "1f2d3c4b", "fileName": "a.txt"}"#) {
+        Err(SwishError::MissingField { path }) => assert_eq!(path, "/fileSizeInBytes"),
+        other => panic!("Expected MissingField, got {:?}", other),
+    }
+    match model::parse::<model::LinksResponse>(b"{\"data\": ") {
+        Err(SwishError::InvalidJson { .. }) => {}
+        other => panic!("Expected InvalidJson, got {:?}", other),
+    }
+}
+
+/// Answers from memory, nothing goes over the network
+struct MemoryTransport {
+    responses: std::collections::HashMap<String, (u32, Vec<u8>)>,
+    requests: std::sync::Mutex<Vec<(Method, String)>>,
+}
+
+impl Transport for MemoryTransport {
+    fn send<'a>(
+        &'a self,
+        request: Request<'a>,
+        sink: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32> {
+        Box::pin(async move {
+            let (status, body) = match self.responses.get(&request.url) {
+                Some(response) => response.clone(),
+                None => (404, Vec::new()),
+            };
+            self.requests
+                .lock()
+                .unwrap()
+                .push((request.method, request.url));
+            if !sink.status(status) {
+                return Err(SwishError::InvalidResponse {
+                    response: String::from("aborted"),
+                });
+            }
+            sink.write(&body)?;
+            progress(request.body.size(), body.len() as u64);
+            Ok(status)
+        })
+    }
+}
+
+#[test]
+fn test_memory_transport() {
+    let endpoint = Endpoint::new("https://swisstransfer.invalid");
+    let link = "188be047-5b8c-48bf-9c4a-e70076c0e53c";
+    let download = "https://dl-4a21b4c5.swisstransfer.com/api/download/188be047-5b8c-48bf-9c4a-e70076c0e53c";
+    let beach: Vec<u8> = (0..2097152u32).map(|i| (i % 253) as u8).collect();
+    let notes: Vec<u8> = b"notes".iter().copied().cycle().take(1048576).collect();
+
+    let mut responses = std::collections::HashMap::new();
+    responses.insert(
+        endpoint.api(&format!("links/{}", link)),
+        (200, read_fixture("links_response.json")),
+    );
+    responses.insert(
+        format!("{}/1f2d3c4b-5a69-4788-9a0b-c1d2e3f40516", download),
+        (200, beach.clone()),
+    );
+    responses.insert(
+        format!("{}/6e5d4c3b-2a19-4807-b6a5-948372615049", download),
+        (200, notes.clone()),
+    );
+    let transport = std::sync::Arc::new(MemoryTransport {
+        responses,
+        requests: Default::default(),
+    });
+
+    let base_path = download_dir("memory_transport");
+    let remote_files = Swissfiles::new_remotefiles_with_transport(
+        &endpoint.download_link(link),
+        None,
+        &endpoint,
+        transport.clone(),
+    )
+    .unwrap();
+    assert_eq!(remote_files.info().total_size, 3145728);
+    remote_files.download(Some(&base_path)).unwrap();
+
+    assert_eq!(std::fs::read(base_path.join("pictures/beach.jpg")).unwrap(), beach);
+    assert_eq!(std::fs::read(base_path.join("pictures/notes.txt")).unwrap(), notes);
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|(method, _)| *method == Method::Get));
+    assert_eq!(requests[0].1, endpoint.api(&format!("links/{}", link)));
+    drop(requests);
+
+    // Anything the server does not know about is a 404 and nothing gets written
+    let result = Swissfiles::new_remotefiles_with_transport(
+        &endpoint.download_link("unknown"),
+        None,
+        &endpoint,
+        transport,
+    );
+    assert!(matches!(result, Err(SwishError::NotFound { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Counts the requests sent through `inner` and the bytes of their bodies
+struct CountingTransport {
+    inner: CurlTransport,
+    requests: std::sync::atomic::AtomicUsize,
+    sent: std::sync::atomic::AtomicU64,
+}
+
+impl Transport for CountingTransport {
+    fn send<'a>(
+        &'a self,
+        request: Request<'a>,
+        sink: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32> {
+        use std::sync::atomic::Ordering;
+
+        assert!(request
+            .headers
+            .contains(&String::from("User-Agent: swisstransfer-webext/1.0")));
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.sent.fetch_add(request.body.size(), Ordering::SeqCst);
+        self.inner.send(request, sink, progress)
+    }
+}
+
+#[test]
+fn test_transport_middleware() {
+    use std::sync::atomic::Ordering;
+
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("transport_middleware");
+    create_folder(&base_path, &[("upload/a.txt", 5000), ("upload/b.txt", 3000)]);
+    let transport = std::sync::Arc::new(CountingTransport {
+        inner: CurlTransport::new(),
+        requests: Default::default(),
+        sent: Default::default(),
+    });
+
+    let local_files = Swissfiles::new_localfiles_with_transport(
+        base_path.join("upload"),
+        &UploadParameters::default(),
+        &endpoint,
+        transport.clone(),
+    )
+    .unwrap();
+    let download_link = local_files.upload().unwrap();
+    // The container, a chunk for each file and the completion
+    assert_eq!(transport.requests.load(Ordering::SeqCst), 4);
+    assert!(transport.sent.load(Ordering::SeqCst) > 8000);
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles_with_transport(
+        &download_link,
+        None,
+        &endpoint,
+        transport.clone(),
+    )
+    .unwrap();
+    remote_files.download(Some(&out_path)).unwrap();
+    // The link and a request for each file
+    assert_eq!(transport.requests.load(Ordering::SeqCst), 7);
+    assert_eq!(std::fs::read(out_path.join("a.txt")).unwrap().len(), 5000);
+    assert_eq!(std::fs::read(out_path.join("b.txt")).unwrap().len(), 3000);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_async_upload_download() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("async_upload_download");
+    create_folder(&base_path, &[("upload/a.txt", 5000), ("upload/sub/b.txt", 3000)]);
+
+    let params = UploadParameters::default();
+    let local_files =
+        Swissfiles::new_localfiles_async(base_path.join("upload"), &params, &endpoint)
+            .await
+            .unwrap();
+    let download_link = local_files.upload_async().await.unwrap();
+
+    let out_path = base_path.join("download");
+    let remote_files = Swissfiles::new_remotefiles_async(&download_link, None, &endpoint)
+        .await
+        .unwrap();
+    let written = remote_files.download_async(Some(&out_path)).await.unwrap();
+    assert_eq!(written.len(), 2);
+    for name in ["a.txt", "sub/b.txt"] {
+        assert_eq!(
+            hash_file(base_path.join("upload").join(name).to_str().unwrap()),
+            hash_file(out_path.join(name).to_str().unwrap())
+        );
+    }
+
+    let link = endpoint.download_link("unknown");
+    let result = Swissfiles::new_remotefiles_async(&link, None, &endpoint).await;
+    assert!(matches!(result, Err(SwishError::NotFound { .. })));
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Never answers, it only waits for the request to be aborted
+#[derive(Default)]
+struct StalledTransport {
+    started: std::sync::atomic::AtomicUsize,
+    aborted: std::sync::atomic::AtomicUsize,
+}
+
+impl Transport for StalledTransport {
+    fn send<'a>(
+        &'a self,
+        _: Request<'a>,
+        _: &'a mut dyn Sink,
+        progress: &'a mut (dyn FnMut(u64, u64) -> bool + Send),
+    ) -> Sending<'a, u32> {
+        Box::pin(async move {
+            self.started
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            // Counted whether the request is refused by `progress` or its future dropped
+            let _aborted = Aborted(&self.aborted);
+            while progress(0, 0) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            Err(SwishError::Interrupted)
+        })
+    }
+}
+
+struct Aborted<'a>(&'a std::sync::atomic::AtomicUsize);
+
+impl Drop for Aborted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_async_cancel_on_drop() {
+    use std::sync::atomic::Ordering;
+
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("async_cancel_on_drop");
+    create_folder(&base_path, &[("a.txt", 5000), ("b.txt", 3000)]);
+    let download_link = upload(&base_path, &UploadParameters::default(), &endpoint);
+
+    let mut remote_files = Swissfiles::new_remotefiles_async(&download_link, None, &endpoint)
+        .await
+        .unwrap();
+    let transport = std::sync::Arc::new(StalledTransport::default());
+    remote_files.transport = transport.clone();
+    remote_files.progress = std::sync::Arc::new(NoProgress);
+
+    let out_path = base_path.join("download");
+    let timeout = std::time::Duration::from_millis(200);
+    let result = tokio::time::timeout(timeout, remote_files.download_async(Some(&out_path))).await;
+    assert!(result.is_err());
+
+    // The request in flight stops and the other file is not even asked for
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while transport.aborted.load(Ordering::SeqCst) == 0 && std::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(transport.started.load(Ordering::SeqCst), 1);
+    assert_eq!(transport.aborted.load(Ordering::SeqCst), 1);
+    assert!(!out_path.join("a.txt").exists());
+    assert!(!out_path.join("b.txt").exists());
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_async_cancel_during_retry() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("async_cancel_during_retry");
+    create_folder(&base_path, &[("a.txt", 5000)]);
+    let transport = std::sync::Arc::new(CountingTransport {
+        inner: CurlTransport::new(),
+        requests: Default::default(),
+        sent: Default::default(),
+    });
+
+    let params = UploadParameters {
+        retry: RetryPolicy {
+            max_attempts: 20,
+            base_delay: std::time::Duration::from_millis(50),
+            max_delay: std::time::Duration::from_millis(50),
+        },
+        ..UploadParameters::default()
+    };
+    let mut local_files = Swissfiles::new_localfiles_async_with_transport(
+        base_path.join("a.txt"),
+        &params,
+        &endpoint,
+        transport.clone(),
+    )
+    .await
+    .unwrap();
+    local_files.progress = std::sync::Arc::new(NoProgress);
+
+    server.inject(Failure::Throttle(100));
+    let timeout = std::time::Duration::from_millis(200);
+    let result = tokio::time::timeout(timeout, local_files.upload_async()).await;
+    assert!(result.is_err());
+
+    // The wait before the next attempt stops too, no other attempt is made
+    let requests = transport.requests.load(std::sync::atomic::Ordering::SeqCst);
+    assert!(requests > 2);
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(transport.requests.load(std::sync::atomic::Ordering::SeqCst), requests);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}
+
+/// Keeps what it is told, bytes are added up per file
+#[derive(Default)]
+struct RecordingObserver {
+    events: std::sync::Mutex<Vec<String>>,
+    bytes: std::sync::Mutex<std::collections::HashMap<String, i64>>,
+}
+
+impl RecordingObserver {
+    fn count(&self, prefix: &str) -> usize {
+        let events = self.events.lock().unwrap();
+        events.iter().filter(|event| event.starts_with(prefix)).count()
+    }
+}
+
+impl ProgressObserver for RecordingObserver {
+    fn on_event(&self, event: ProgressEvent) {
+        let mut bytes = self.bytes.lock().unwrap();
+        let recorded = match event {
+            ProgressEvent::BytesTransferred { file, bytes: sent } => {
+                *bytes.entry(file.to_string()).or_default() += sent as i64;
+                return;
+            }
+            ProgressEvent::BytesRewound { file, bytes: rewound } => {
+                *bytes.entry(file.to_string()).or_default() -= rewound as i64;
+                format!("rewound {}", file)
+            }
+            ProgressEvent::TransferStarted {
+                direction,
+                files,
+                size,
+                done,
+            } => format!("started {:?} {} {} {}", direction, files, size, done),
+            ProgressEvent::FileStarted { file, size } => format!("file {} {}", file, size),
+            ProgressEvent::ChunkSent { file, chunk, .. } => format!("chunk {} {}", file, chunk),
+            ProgressEvent::Retrying { file, chunk, attempt, .. } => {
+                format!("retrying {} {} {}", file, chunk, attempt)
+            }
+            ProgressEvent::FileCompleted { file, skipped } => format!("completed {} {}", file, skipped),
+            ProgressEvent::FileFailed { file, error } => format!("failed {} {}", file, error.code()),
+            ProgressEvent::Finished { error } => format!("finished {}", error.is_none()),
+        };
+        self.events.lock().unwrap().push(recorded);
+    }
+}
+
+#[test]
+fn test_progress_events() {
+    let server = FakeServer::start();
+    let endpoint = server.endpoint();
+    let base_path = download_dir("progress_events");
+    create_folder(&base_path, &[("upload/a.txt", 5000), ("upload/b.txt", 3000)]);
+
+    let params = UploadParameters {
+        retry: fast_retry(3),
+        ..Default::default()
+    };
+    let mut local_files =
+        Swissfiles::new_localfiles(base_path.join("upload"), &params, &endpoint).unwrap();
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    local_files.progress = observer.clone();
+    server.inject(Failure::ChunkUnavailable(1));
+    let download_link = local_files.upload().unwrap();
+
+    {
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events.first().unwrap(), &format!("started {:?} 2 8000 0", Direction::Upload));
+        assert_eq!(events.last().unwrap(), "finished true");
+    }
+    assert_eq!(observer.count("file "), 2);
+    assert_eq!(observer.count("chunk "), 2);
+    assert_eq!(observer.count("retrying a.txt 0 1"), 1);
+    assert_eq!(observer.count("completed a.txt false"), 1);
+    assert_eq!(observer.count("completed b.txt false"), 1);
+    // What was sent by the failed attempt has been taken back
+    let bytes = observer.bytes.lock().unwrap().clone();
+    assert_eq!(bytes["a.txt"], 5000);
+    assert_eq!(bytes["b.txt"], 3000);
+
+    let out_path = base_path.join("download");
+    std::fs::create_dir_all(&out_path).unwrap();
+    std::fs::write(out_path.join("b.txt"), "already there").unwrap();
+    let mut remote_files = Swissfiles::new_remotefiles(&download_link, None, &endpoint).unwrap();
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    remote_files.progress = observer.clone();
+    remote_files.on_conflict = OnConflict::Skip;
+    remote_files.download(Some(&out_path)).unwrap();
+
+    {
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events.first().unwrap(), &format!("started {:?} 2 8000 0", Direction::Download));
+        assert_eq!(events.last().unwrap(), "finished true");
+    }
+    assert_eq!(observer.count("file a.txt 5000"), 1);
+    assert_eq!(observer.count("completed a.txt false"), 1);
+    assert_eq!(observer.count("completed b.txt true"), 1);
+    assert_eq!(observer.count("chunk "), 0);
+    assert_eq!(observer.bytes.lock().unwrap()["a.txt"], 5000);
+
+    // A failed file is reported as such, the transfer too
+    server.inject(Failure::MissingFile(String::from("a.txt")));
+    std::fs::remove_file(out_path.join("a.txt")).unwrap();
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    remote_files.progress = observer.clone();
+    assert!(remote_files.download(Some(&out_path)).is_err());
+    assert_eq!(observer.count("failed a.txt"), 1);
+    assert_eq!(observer.count("finished false"), 1);
+    std::fs::remove_dir_all(&base_path).unwrap();
+}